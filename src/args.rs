@@ -0,0 +1,97 @@
+use std::path::PathBuf;
+
+/// How to run the program, for `--help`
+pub const USAGE: &str = "\
+Usage: tiralabra-calculator [options] [file]
+
+Calculates the expressions given with -e, the lines of <file>, or the lines of the standard
+input if it isn't a terminal, and prints the results. Otherwise starts the interactive
+calculator.
+
+Options:
+  -e, --eval <expr>     Calculate <expr>, can be given more than once
+  --keep-going          Don't stop at the first error
+  --history <path>      Save the input history to <path> (or $TIRALABRA_HISTORY)
+  --history-size <n>    Keep at most <n> lines of history, 0 to keep none
+                        (or $TIRALABRA_HISTORY_SIZE, 1000 by default)
+  -h, --help            Show this message";
+
+/// The command line arguments
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Args {
+    pub help: bool,
+    /// The expressions given with `-e`, in order
+    pub expressions: Vec<String>,
+    /// The script to calculate
+    pub file: Option<PathBuf>,
+    pub keep_going: bool,
+    pub history: Option<PathBuf>,
+    pub history_size: Option<usize>,
+}
+
+/// Parses the arguments, not including the name of the program.
+/// Returns an error message for an unknown option or a missing or bad value.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} requires a value"));
+        match arg.as_str() {
+            "-h" | "--help" => parsed.help = true,
+            "-e" | "--eval" => parsed.expressions.push(value()?),
+            "--keep-going" => parsed.keep_going = true,
+            "--history" => parsed.history = Some(PathBuf::from(value()?)),
+            "--history-size" => {
+                let size = value()?;
+                let size = size.parse().map_err(|_| format!("Not a valid history size: {size}"))?;
+                parsed.history_size = Some(size);
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown argument: {arg}")),
+            _ if parsed.file.is_some() => return Err(format!("Unexpected argument: {arg}")),
+            _ => parsed.file = Some(PathBuf::from(arg)),
+        }
+    }
+    if parsed.file.is_some() && !parsed.expressions.is_empty() {
+        return Err("Give either expressions with -e or a file, not both".to_string());
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod args_tests {
+    use super::*;
+
+    fn parse_str(args: &[&str]) -> Result<Args, String> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn options() {
+        assert_eq!(parse_str(&[]), Ok(Args::default()));
+        let args = parse_str(&["--history", "/tmp/h", "--history-size", "10"]).unwrap();
+        assert_eq!(args.history, Some(PathBuf::from("/tmp/h")));
+        assert_eq!(args.history_size, Some(10));
+        assert!(parse_str(&["-h"]).unwrap().help);
+    }
+
+    #[test]
+    fn batch_mode() {
+        let args = parse_str(&["-e", "1 + 2", "--eval", "ans", "--keep-going"]).unwrap();
+        assert_eq!(args.expressions, ["1 + 2", "ans"]);
+        assert!(args.keep_going);
+        assert_eq!(parse_str(&["script.calc"]).unwrap().file, Some(PathBuf::from("script.calc")));
+
+        assert_eq!(parse_str(&["a", "b"]), Err("Unexpected argument: b".to_string()));
+        assert!(parse_str(&["-e", "1", "script.calc"]).is_err());
+    }
+
+    #[test]
+    fn bad_arguments() {
+        assert_eq!(parse_str(&["--history"]), Err("--history requires a value".to_string()));
+        assert_eq!(
+            parse_str(&["--history-size", "-1"]),
+            Err("Not a valid history size: -1".to_string())
+        );
+        assert_eq!(parse_str(&["--foo"]), Err("Unknown argument: --foo".to_string()));
+    }
+}
@@ -0,0 +1,116 @@
+use std::io::{self, Write};
+
+use crate::commands::{self, Reply};
+use tiralabra_calculator::Calculator;
+
+/// Calculates `lines` one by one with the same calculator, like the REPL would, but without
+/// the prompt: results (and the output of `?` commands) are written to `out`, and errors to
+/// `err` as `source:line:column: message`. Empty lines are skipped, and `?quit` stops early.
+/// There is no input history, so `?history clear` only forgets the results.
+///
+/// Stops at the first error unless `keep_going` is set. Returns whether every line succeeded,
+/// or the error from reading a line or writing the output.
+pub fn run(
+    source: &str,
+    lines: impl IntoIterator<Item = io::Result<String>>,
+    keep_going: bool,
+    out: &mut impl Write,
+    err: &mut impl Write,
+) -> io::Result<bool> {
+    let mut calculator = Calculator::new();
+    let mut success = true;
+
+    for (i, line) in lines.into_iter().enumerate() {
+        let line = line?;
+        let number = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(command) = line.trim().strip_prefix('?') {
+            match commands::run(&mut calculator, command) {
+                Ok(Reply::Print(lines)) => {
+                    for line in lines {
+                        writeln!(out, "{line}")?;
+                    }
+                }
+                Ok(Reply::Quit) => break,
+                Ok(Reply::ClearHistory) => writeln!(out, "Cleared the results")?,
+                Err(message) => {
+                    writeln!(err, "{source}:{number}: {message}")?;
+                    success = false;
+                }
+            }
+        } else {
+            match calculator.calculate_infix(&line) {
+                Ok(result) => writeln!(out, "{result}")?,
+                Err(error) => {
+                    let column = line[..error.span().start].chars().count() + 1;
+                    writeln!(err, "{source}:{number}:{column}: {error}")?;
+                    success = false;
+                }
+            }
+        }
+
+        if !success && !keep_going {
+            break;
+        }
+    }
+    Ok(success)
+}
+
+#[cfg(test)]
+mod batch_tests {
+    use super::*;
+
+    /// What `run` returns and writes to `out` and `err`
+    fn batch(input: &str, keep_going: bool) -> (bool, String, String) {
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let lines = input.lines().map(|line| Ok(line.to_string()));
+        let success = run("test", lines, keep_going, &mut out, &mut err).unwrap();
+        (success, String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap())
+    }
+
+    #[test]
+    fn lines_are_calculated_in_order() {
+        let (success, out, err) = batch("a = 2\n\n?deg\nsin(90) + a\n", false);
+        assert!(success);
+        assert_eq!(out, "2\nAngles are in degrees\n3\n");
+        assert_eq!(err, "");
+    }
+
+    #[test]
+    fn stops_at_first_error() {
+        let (success, out, err) = batch("1\n1 + foo\n?bar\n2", false);
+        assert!(!success);
+        assert_eq!(out, "1\n");
+        assert_eq!(err, "test:2:5: Undefined variable: foo\n");
+    }
+
+    #[test]
+    fn keeps_going() {
+        let (success, out, err) = batch("1\n1 + foo\n?bar\n2", true);
+        assert!(!success);
+        assert_eq!(out, "1\n2\n");
+        let lines: Vec<&str> = err.lines().collect();
+        assert_eq!(lines, [
+            "test:2:5: Undefined variable: foo",
+            "test:3: Unknown command: ?bar (see ?help)",
+        ]);
+    }
+
+    #[test]
+    fn history_clear_forgets_the_results() {
+        let (success, out, err) = batch("1\n?history clear\n2\n$1", false);
+        assert!(success);
+        assert_eq!(out, "1\nCleared the results\n2\n2\n");
+        assert_eq!(err, "");
+    }
+
+    #[test]
+    fn quit_stops() {
+        let (success, out, _) = batch("1\n?quit\n2", false);
+        assert!(success);
+        assert_eq!(out, "1\n");
+    }
+}
@@ -0,0 +1,381 @@
+use tiralabra_calculator::{Calculator, Function, CONSTANTS};
+
+/// What the REPL should do after a command
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reply {
+    /// Print the lines
+    Print(Vec<String>),
+    Quit,
+    /// The results have been cleared, and the REPL should also forget the lines entered so
+    /// far (the input history), also from the history file
+    ClearHistory,
+}
+
+/// A command of the REPL, written after the `?` prefix, e.g. `?del x`
+struct Command {
+    name: &'static str,
+    /// The arguments of the command for `?help`, e.g. "<name>"
+    usage: &'static str,
+    /// One line about what the command does
+    help: &'static str,
+    /// Runs the command with the rest of the line as the arguments
+    run: fn(&mut Calculator, &str) -> Result<Reply, String>,
+}
+
+/// Shorthand for the table
+const fn command(
+    name: &'static str,
+    usage: &'static str,
+    help: &'static str,
+    run: fn(&mut Calculator, &str) -> Result<Reply, String>,
+) -> Command {
+    Command { name, usage, help, run }
+}
+
+/// All the commands, in the order `?help` lists them
+static COMMANDS: &[Command] = &[
+    command("help", "[command]", "Show the commands, or help about one", help),
+    command("quit", "", "Exit the calculator", quit),
+    command("vars", "", "List the variables", vars),
+    command("funcs", "", "List the built-in and the defined functions", funcs),
+    command("consts", "", "List the constants", consts),
+    command("history", "[clear]", "List the results ($1, $2, ...), or clear all history", history),
+    command("del", "<name>", "Delete a variable or a function", del),
+    command("clear", "", "Delete all variables and functions", clear),
+    command("reset", "", "Start over, deleting the history too and restoring the settings", reset),
+    command("save", "<file>", "Save the variables, functions and settings to a file", save),
+    command("load", "<file>", "Replace the variables, functions and settings from a file", load),
+    command("format", "[settings]", "Show or change how results are shown, e.g. sci 6", format),
+    command("exact", "", "Calculate with exact fractions", exact),
+    command("float", "", "Calculate with floating point numbers", float),
+    command("fraction", "", "Show exact results as fractions", fraction),
+    command("decimal", "", "Show exact results as decimals", decimal),
+    command("deg", "", "Use degrees in trigonometric functions", deg),
+    command("rad", "", "Use radians in trigonometric functions", rad),
+    command("grad", "", "Use gradians in trigonometric functions", grad),
+    command("pocket", "", "Make x + 10% mean x * 1.1", pocket),
+    command("plain", "", "Make x + 10% mean x + 0.1", plain),
+    command("implicit", "[off|normal|tight]", "Show or change how 2x is multiplied", implicit),
+];
+
+/// Runs the command on the line `command`, which is everything after the `?` prefix.
+///
+/// Returns what to print (or that the REPL should quit), or an error message for an unknown
+/// command or bad arguments.
+pub fn run(calculator: &mut Calculator, command: &str) -> Result<Reply, String> {
+    let command = command.trim();
+    let (name, args) = command.split_once(' ').unwrap_or((command, ""));
+    match COMMANDS.iter().find(|command| command.name == name) {
+        Some(command) => (command.run)(calculator, args.trim()),
+        None => Err(format!("Unknown command: ?{name} (see ?help)")),
+    }
+}
+
+/// The names of all commands, without the `?` prefix
+pub fn names() -> impl Iterator<Item = &'static str> {
+    COMMANDS.iter().map(|command| command.name)
+}
+
+/// A reply of just one line
+fn line(text: impl Into<String>) -> Result<Reply, String> {
+    Ok(Reply::Print(vec![text.into()]))
+}
+
+/// An error if there are arguments for a command that takes none
+fn no_args(args: &str) -> Result<(), String> {
+    if args.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Unexpected arguments: {args}"))
+    }
+}
+
+fn quit(_: &mut Calculator, args: &str) -> Result<Reply, String> {
+    no_args(args)?;
+    Ok(Reply::Quit)
+}
+
+fn help(_: &mut Calculator, topic: &str) -> Result<Reply, String> {
+    if topic.is_empty() {
+        let width = COMMANDS.iter().map(|command| usage(command).len()).max().unwrap_or(0);
+        let lines = COMMANDS
+            .iter()
+            .map(|command| format!("{:width$}  {}", usage(command), command.help))
+            .collect();
+        return Ok(Reply::Print(lines));
+    }
+
+    let name = topic.trim_start_matches('?');
+    match COMMANDS.iter().find(|command| command.name == name) {
+        Some(command) => Ok(Reply::Print(vec![usage(command), command.help.to_string()])),
+        None => Err(format!("No help for {topic}, try ?help")),
+    }
+}
+
+/// How the command is written, e.g. "?del <name>"
+fn usage(command: &Command) -> String {
+    format!("?{} {}", command.name, command.usage).trim_end().to_string()
+}
+
+fn vars(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    no_args(args)?;
+    let lines: Vec<String> = calculator
+        .variables()
+        .into_iter()
+        .map(|(name, value)| format!("{name} = {}", calculator.format_value(value)))
+        .collect();
+    if lines.is_empty() {
+        return line("No variables");
+    }
+    Ok(Reply::Print(lines))
+}
+
+fn funcs(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    no_args(args)?;
+    let names: Vec<&str> = Function::all().map(Function::format).collect();
+    let mut lines: Vec<String> = names.chunks(10).map(|names| names.join(", ")).collect();
+    lines.extend(calculator.functions().into_iter().map(str::to_string));
+    Ok(Reply::Print(lines))
+}
+
+fn consts(_: &mut Calculator, args: &str) -> Result<Reply, String> {
+    no_args(args)?;
+    let lines = CONSTANTS.iter().map(|(name, value)| format!("{name} = {value}")).collect();
+    Ok(Reply::Print(lines))
+}
+
+fn history(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    match args {
+        "" => {}
+        "clear" => {
+            calculator.clear_history();
+            return Ok(Reply::ClearHistory);
+        }
+        _ => return Err("Expected ?history or ?history clear".to_string()),
+    }
+    let lines = calculator
+        .history()
+        .iter()
+        .enumerate()
+        .map(|(i, value)| format!("${} = {}", i + 1, calculator.format_value(value)))
+        .collect();
+    Ok(Reply::Print(lines))
+}
+
+fn del(calculator: &mut Calculator, name: &str) -> Result<Reply, String> {
+    if name.is_empty() {
+        return Err("Expected the name of a variable or a function".to_string());
+    }
+    if calculator.remove(name) {
+        line(format!("Deleted {name}"))
+    } else {
+        Err(format!("No variable or function called {name}"))
+    }
+}
+
+fn clear(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    no_args(args)?;
+    calculator.clear();
+    line("Deleted all variables and functions")
+}
+
+fn reset(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    no_args(args)?;
+    *calculator = Calculator::new();
+    line("Started over")
+}
+
+fn save(calculator: &mut Calculator, path: &str) -> Result<Reply, String> {
+    if path.is_empty() {
+        return Err("Expected the name of the file".to_string());
+    }
+    std::fs::write(path, calculator.save()).map_err(|err| format!("Couldn't write {path}: {err}"))?;
+    line(format!("Saved the session to {path}"))
+}
+
+fn load(calculator: &mut Calculator, path: &str) -> Result<Reply, String> {
+    if path.is_empty() {
+        return Err("Expected the name of the file".to_string());
+    }
+    let session =
+        std::fs::read_to_string(path).map_err(|err| format!("Couldn't read {path}: {err}"))?;
+    calculator.load(&session).map_err(|(line, err)| format!("{path}, line {line}: {err}"))?;
+    line(format!("Loaded the session from {path}"))
+}
+
+fn format(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    calculator.apply_setting(&format!("format {args}"))?;
+    line(format!("Format: {}", calculator.format()))
+}
+
+/// Changes the setting `name`, which takes no arguments, with `Calculator::apply_setting`
+fn setting(calculator: &mut Calculator, name: &str, args: &str) -> Result<(), String> {
+    no_args(args)?;
+    calculator.apply_setting(name)
+}
+
+fn exact(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    setting(calculator, "exact", args)?;
+    line("Using exact fractions")
+}
+
+fn float(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    setting(calculator, "float", args)?;
+    line("Using floating point numbers")
+}
+
+fn fraction(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    setting(calculator, "fraction", args)?;
+    line("Showing exact results as fractions")
+}
+
+fn decimal(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    setting(calculator, "decimal", args)?;
+    line("Showing exact results as decimals")
+}
+
+fn deg(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    setting(calculator, "deg", args)?;
+    line("Angles are in degrees")
+}
+
+fn rad(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    setting(calculator, "rad", args)?;
+    line("Angles are in radians")
+}
+
+fn grad(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    setting(calculator, "grad", args)?;
+    line("Angles are in gradians")
+}
+
+fn pocket(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    setting(calculator, "pocket", args)?;
+    line("x + 10% is x * 1.1")
+}
+
+fn plain(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    setting(calculator, "plain", args)?;
+    line("x + 10% is x + 0.1")
+}
+
+fn implicit(calculator: &mut Calculator, args: &str) -> Result<Reply, String> {
+    if !args.is_empty() {
+        calculator.apply_setting(&format!("implicit {args}"))?;
+    }
+    line(format!("Implicit multiplication: {}", calculator.implicit_multiplication()))
+}
+
+#[cfg(test)]
+mod commands_tests {
+    use super::*;
+    use tiralabra_calculator::{AngleMode, ImplicitMultiplication, NumberMode};
+
+    /// The printed lines of a successful command
+    fn lines(calculator: &mut Calculator, command: &str) -> Vec<String> {
+        match run(calculator, command) {
+            Ok(Reply::Print(lines)) => lines,
+            other => panic!("?{command} gave {other:?}"),
+        }
+    }
+
+    #[test]
+    fn unknown_commands_are_errors() {
+        let mut calculator = Calculator::new();
+        let err = run(&mut calculator, "foo").unwrap_err();
+        assert_eq!(err, "Unknown command: ?foo (see ?help)");
+        assert!(run(&mut calculator, "").is_err());
+        assert!(run(&mut calculator, "quit now").is_err());
+        assert_eq!(run(&mut calculator, "quit"), Ok(Reply::Quit));
+    }
+
+    #[test]
+    fn help_lists_every_command() {
+        let mut calculator = Calculator::new();
+        let help = lines(&mut calculator, "help");
+        assert_eq!(help.len(), COMMANDS.len());
+        assert!(help[0].starts_with("?help [command]"));
+
+        assert_eq!(lines(&mut calculator, "help del")[0], "?del <name>");
+        assert_eq!(lines(&mut calculator, "help ?quit")[0], "?quit");
+        assert!(run(&mut calculator, "help foo").is_err());
+    }
+
+    #[test]
+    fn variables_and_functions() {
+        let mut calculator = Calculator::new();
+        assert_eq!(lines(&mut calculator, "vars"), ["No variables"]);
+
+        calculator.calculate_infix("b = 2").unwrap();
+        calculator.calculate_infix("a = 1/4").unwrap();
+        calculator.calculate_infix("f(x) = x + b").unwrap();
+        assert_eq!(lines(&mut calculator, "vars"), ["a = 0.25", "b = 2"]);
+        assert_eq!(lines(&mut calculator, "funcs").last().unwrap(), "f(x) = x + b");
+        assert!(lines(&mut calculator, "funcs")[0].starts_with("sin, cos, tan"));
+
+        assert_eq!(lines(&mut calculator, "del b"), ["Deleted b"]);
+        assert!(calculator.calculate_infix("f(1)").is_err());
+        assert!(run(&mut calculator, "del b").is_err());
+        assert!(run(&mut calculator, "del").is_err());
+
+        lines(&mut calculator, "del f");
+        assert!(calculator.calculate_infix("f(1)").is_err());
+    }
+
+    #[test]
+    fn clear_and_reset() {
+        let mut calculator = Calculator::new();
+        calculator.calculate_infix("a = 1").unwrap();
+        run(&mut calculator, "deg").unwrap();
+        run(&mut calculator, "clear").unwrap();
+        assert!(calculator.calculate_infix("a").is_err());
+        assert_eq!(lines(&mut calculator, "history"), ["$1 = 1"]);
+        assert_eq!(run(&mut calculator, "history clear"), Ok(Reply::ClearHistory));
+        assert!(calculator.history().is_empty());
+        assert!(run(&mut calculator, "history all").is_err());
+        assert_eq!(calculator.angle_mode(), AngleMode::Degrees);
+
+        run(&mut calculator, "reset").unwrap();
+        assert!(calculator.history().is_empty());
+        assert_eq!(calculator.angle_mode(), AngleMode::Radians);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("tiralabra-session-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut calculator = Calculator::new();
+        calculator.calculate_infix("a = 2").unwrap();
+        run(&mut calculator, "deg").unwrap();
+        run(&mut calculator, &format!("save {path}")).unwrap();
+
+        let mut restored = Calculator::new();
+        assert_eq!(lines(&mut restored, &format!("load {path}")), [
+            format!("Loaded the session from {path}")
+        ]);
+        assert_eq!(restored.calculate_infix("a").unwrap(), "2");
+        assert_eq!(restored.angle_mode(), AngleMode::Degrees);
+
+        std::fs::write(path, "a = 1\nb = (").unwrap();
+        let err = run(&mut restored, &format!("load {path}")).unwrap_err();
+        assert!(err.ends_with("line 2: Left parenthesis without a pair found"), "{err}");
+        std::fs::remove_file(path).unwrap();
+
+        assert!(run(&mut restored, "load").is_err());
+        assert!(run(&mut restored, &format!("load {path}")).is_err());
+    }
+
+    #[test]
+    fn settings() {
+        let mut calculator = Calculator::new();
+        assert_eq!(lines(&mut calculator, "format sci 3"), ["Format: sci 3 dec nogroup"]);
+        assert!(run(&mut calculator, "format foo").is_err());
+        run(&mut calculator, "exact").unwrap();
+        assert_eq!(calculator.number_mode(), NumberMode::Exact);
+        assert_eq!(lines(&mut calculator, "implicit"), ["Implicit multiplication: normal"]);
+        run(&mut calculator, "implicit off").unwrap();
+        assert_eq!(calculator.implicit_multiplication(), ImplicitMultiplication::Off);
+        assert!(run(&mut calculator, "implicit sometimes").is_err());
+    }
+}
@@ -0,0 +1,315 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::commands;
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hint, Hinter};
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+use tiralabra_calculator::{
+    tokenize_spanned, Calculator, Function, Operator::*, Span, Token, CONSTANTS,
+};
+
+const RESET: &str = "\x1b[0m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const BLUE: &str = "\x1b[34m";
+const CYAN: &str = "\x1b[36m";
+const GREY: &str = "\x1b[90m";
+/// For a parenthesis and its pair when the cursor is on (or right after) one of them
+const MATCHING: &str = "\x1b[1;35m";
+
+/// Colours the input, completes names with tab and shows the result of the line while it's
+/// being typed. Shares the calculator with the REPL, so that newly defined variables and
+/// functions are found right away.
+pub struct CalcHelper {
+    calculator: Rc<RefCell<Calculator>>,
+}
+
+impl CalcHelper {
+    pub fn new(calculator: Rc<RefCell<Calculator>>) -> CalcHelper {
+        CalcHelper { calculator }
+    }
+}
+
+impl Helper for CalcHelper {}
+
+impl Validator for CalcHelper {}
+
+impl Completer for CalcHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = word_start(line, pos);
+        let prefix = &line[start..pos];
+        let candidates = if line[..start].trim_start() == "?" {
+            commands::names()
+                .filter(|name| name.starts_with(prefix))
+                .map(|name| Pair { display: name.to_string(), replacement: name.to_string() })
+                .collect()
+        } else if prefix.is_empty() {
+            Vec::new()
+        } else {
+            candidates(&self.calculator.borrow(), prefix)
+        };
+        Ok((start, candidates))
+    }
+}
+
+/// The result shown after the cursor. Unlike a `String` hint, it can't be accepted into the
+/// line with the right arrow key.
+pub struct ResultHint(String);
+
+impl Hint for ResultHint {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Hinter for CalcHelper {
+    type Hint = ResultHint;
+
+    fn hint(&self, line: &str, pos: usize, _: &Context<'_>) -> Option<ResultHint> {
+        // only when the cursor is at the end, or the hint would be in the middle of the line
+        if pos < line.len() {
+            return None;
+        }
+        live_result(&self.calculator.borrow(), line).map(ResultHint)
+    }
+}
+
+impl Highlighter for CalcHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if line.trim_start().starts_with('?') {
+            return Cow::Borrowed(line);
+        }
+        Cow::Owned(highlight(line, pos))
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("{GREY}{hint}{RESET}"))
+    }
+
+    // the matching parenthesis changes when the cursor moves
+    fn highlight_char(&self, _: &str, _: usize) -> bool {
+        true
+    }
+}
+
+/// Where the name (or number) ending at `pos` starts
+fn word_start(line: &str, pos: usize) -> usize {
+    line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| c.is_alphanumeric() || c == '_')
+        .last()
+        .map_or(pos, |(i, _)| i)
+}
+
+/// The functions, constants and variables starting with `prefix`, in alphabetical order.
+/// Functions are completed with the opening parenthesis.
+fn candidates(calculator: &Calculator, prefix: &str) -> Vec<Pair> {
+    let functions = Function::all()
+        .map(|function| function.format())
+        .chain(calculator.functions().into_iter().filter_map(|definition| {
+            definition.split_once('(').map(|(name, _)| name)
+        }))
+        .map(|name| (name, format!("{name}(")));
+    let variables = CONSTANTS
+        .iter()
+        .map(|&(name, _)| name)
+        .chain(calculator.variables().into_iter().map(|(name, _)| name))
+        .chain(["ans"])
+        .map(|name| (name, name.to_string()));
+
+    let mut candidates: Vec<Pair> = functions
+        .chain(variables)
+        .filter(|(name, _)| name.starts_with(prefix))
+        .map(|(name, replacement)| Pair { display: name.to_string(), replacement })
+        .collect();
+    candidates.sort_by(|a, b| a.display.cmp(&b.display));
+    candidates.dedup_by(|a, b| a.display == b.display);
+    candidates
+}
+
+/// The hint for `line`, e.g. " = 3" for "1 + 2". Nothing for commands, for input that
+/// can't be calculated (yet), or when the result is the same as the input.
+fn live_result(calculator: &Calculator, line: &str) -> Option<String> {
+    let input = line.trim();
+    if input.is_empty() || input.starts_with('?') {
+        return None;
+    }
+    let result = calculator.format_value(&calculator.preview(line).ok()?);
+    (result != input).then(|| format!(" = {result}"))
+}
+
+/// Returns `line` with ANSI colours: numbers are yellow, operators cyan, functions blue and
+/// variables green. If the line can't be tokenized, the part with the error is red instead.
+/// The parenthesis at `pos` (or right before it) and its pair are highlighted.
+fn highlight(line: &str, pos: usize) -> String {
+    let tokens = match tokenize_spanned(line) {
+        Ok(tokens) => tokens,
+        Err(err) => return paint(line, &[(err.span().clone(), RED)]),
+    };
+    let matching = matching_parens(&tokens, pos);
+
+    let colours: Vec<(Span, &str)> = tokens
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (token, span))| {
+            let colour = match token {
+                _ if matching.contains(&i) => MATCHING,
+                Token::Number(_) | Token::History(_) | Token::ImaginaryUnit => YELLOW,
+                Token::Variable(_) => GREEN,
+                Token::Op(Func(..) | UserFunc(..)) => BLUE,
+                Token::Op(Lparen | Rparen | Comma | Equals) => return None,
+                Token::Op(_) => CYAN,
+                Token::JumpIfFalse(_) | Token::Jump(_) => return None,
+            };
+            Some((span.clone(), colour))
+        })
+        .collect();
+    paint(line, &colours)
+}
+
+/// The indices of the parenthesis at `pos` and its pair. If there's no parenthesis at `pos`,
+/// the one right before it is used, as the cursor is usually after what was just typed.
+fn matching_parens(tokens: &[(Token, Span)], pos: usize) -> Vec<usize> {
+    let is_paren = |token: &Token| matches!(token, Token::Op(Lparen | Rparen));
+    let at = |offset: usize| {
+        tokens.iter().position(|(token, span)| span.start + offset == pos && is_paren(token))
+    };
+    let Some(i) = at(0).or_else(|| at(1)) else {
+        return Vec::new();
+    };
+
+    let mut depth = 0;
+    let pair = if tokens[i].0 == Token::Op(Lparen) {
+        (i..tokens.len()).find(|&j| {
+            depth += paren_depth(&tokens[j].0);
+            depth == 0
+        })
+    } else {
+        (0..=i).rev().find(|&j| {
+            depth += paren_depth(&tokens[j].0);
+            depth == 0
+        })
+    };
+    pair.map_or_else(Vec::new, |j| vec![i, j])
+}
+
+/// How a token changes the nesting depth of parentheses
+fn paren_depth(token: &Token) -> i32 {
+    match token {
+        Token::Op(Lparen) => 1,
+        Token::Op(Rparen) => -1,
+        _ => 0,
+    }
+}
+
+/// Wraps the given spans of `line` in colours. The spans have to be in order and not overlap.
+fn paint(line: &str, colours: &[(Span, &str)]) -> String {
+    let mut output = String::with_capacity(line.len() * 2);
+    let mut end = 0;
+    for (span, colour) in colours {
+        output.push_str(&line[end..span.start]);
+        output.push_str(colour);
+        output.push_str(&line[span.clone()]);
+        output.push_str(RESET);
+        end = span.end;
+    }
+    output.push_str(&line[end..]);
+    output
+}
+
+#[cfg(test)]
+mod helper_tests {
+    use super::*;
+
+    fn names(calculator: &Calculator, prefix: &str) -> Vec<String> {
+        candidates(calculator, prefix).into_iter().map(|pair| pair.replacement).collect()
+    }
+
+    #[test]
+    fn tokens_are_coloured() {
+        let res = highlight("2 + sin(x)", 10);
+        assert_eq!(
+            res,
+            format!(
+                "{YELLOW}2{RESET} {CYAN}+{RESET} {BLUE}sin{RESET}{MATCHING}({RESET}\
+                 {GREEN}x{RESET}{MATCHING}){RESET}"
+            )
+        );
+    }
+
+    #[test]
+    fn lexing_error_is_red() {
+        assert_eq!(highlight("1 + #", 5), format!("1 + {RED}#{RESET}"));
+    }
+
+    #[test]
+    fn parentheses_are_matched() {
+        let tokens = tokenize_spanned("(1 + (2)) * 3").unwrap();
+        assert_eq!(matching_parens(&tokens, 0), vec![0, 6]);
+        assert_eq!(matching_parens(&tokens, 9), vec![6, 0]);
+        assert_eq!(matching_parens(&tokens, 7), vec![5, 3]);
+        assert!(matching_parens(&tokens, 2).is_empty());
+
+        let tokens = tokenize_spanned("(1 + 2").unwrap();
+        assert!(matching_parens(&tokens, 0).is_empty());
+    }
+
+    #[test]
+    fn names_are_completed() {
+        let mut calculator = Calculator::new();
+        calculator.calculate_infix("area = 2").unwrap();
+        calculator.calculate_infix("avg(a, b) = (a + b) / 2").unwrap();
+
+        assert_eq!(names(&calculator, "a"), [
+            "abs(", "acos(", "acosh(", "ans", "area", "arg(", "asin(", "asinh(", "atan(",
+            "atan2(", "atanh(", "avg(",
+        ]);
+        assert_eq!(names(&calculator, "pi"), ["pi"]);
+        assert!(names(&calculator, "q").is_empty());
+    }
+
+    #[test]
+    fn word_before_cursor() {
+        assert_eq!(word_start("1 + sq", 6), 4);
+        assert_eq!(word_start("max(a_b", 7), 4);
+        assert_eq!(word_start("1 + ", 4), 4);
+    }
+
+    #[test]
+    fn result_is_hinted() {
+        let mut calculator = Calculator::new();
+        calculator.calculate_infix("a = 4").unwrap();
+
+        assert_eq!(live_result(&calculator, "a * 2").as_deref(), Some(" = 8"));
+        assert_eq!(live_result(&calculator, "b = a + 1").as_deref(), Some(" = 5"));
+        assert_eq!(live_result(&calculator, "2"), None);
+        assert_eq!(live_result(&calculator, "1 +"), None);
+        assert_eq!(live_result(&calculator, "f(x) = x"), None);
+        assert_eq!(live_result(&calculator, "?vars"), None);
+        assert!(calculator.history().len() == 1);
+    }
+
+    #[test]
+    fn slow_results_are_not_hinted() {
+        let calculator = Calculator::new();
+        assert_eq!(live_result(&calculator, "50000!"), None);
+        assert_eq!(live_result(&calculator, "3^50000"), None);
+    }
+}
@@ -0,0 +1,143 @@
+use std::fs::OpenOptions;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use rustyline::error::ReadlineError;
+use rustyline::{Editor, Helper};
+
+/// The environment variable for the path of the history file
+const PATH_VAR: &str = "TIRALABRA_HISTORY";
+/// The environment variable for the number of lines kept in the history
+const SIZE_VAR: &str = "TIRALABRA_HISTORY_SIZE";
+/// How many lines are kept if nothing else is said
+const DEFAULT_SIZE: usize = 1000;
+
+/// Where the input history is kept between sessions, and how many lines of it
+#[derive(Debug, PartialEq, Eq)]
+pub struct HistoryFile {
+    /// `None` if there's nowhere to save the history (no home directory)
+    pub path: Option<PathBuf>,
+    pub size: usize,
+}
+
+impl HistoryFile {
+    /// Uses the command line options if given, then the environment variables, and finally
+    /// the defaults: 1000 lines in `$XDG_DATA_HOME/tiralabra-calculator/history`
+    /// (usually `~/.local/share/...`).
+    pub fn new(path: Option<PathBuf>, size: Option<usize>) -> Result<HistoryFile, String> {
+        let env_var = |name| std::env::var(name).ok().filter(|value: &String| !value.is_empty());
+        let default_path =
+            || dirs_next::data_dir().map(|dir| dir.join("tiralabra-calculator").join("history"));
+
+        let path = path.or_else(|| env_var(PATH_VAR).map(PathBuf::from)).or_else(default_path);
+        let size = match (size, env_var(SIZE_VAR)) {
+            (Some(size), _) => size,
+            (None, Some(size)) => size
+                .parse()
+                .map_err(|_| format!("Not a valid history size in ${SIZE_VAR}: {size}"))?,
+            (None, None) => DEFAULT_SIZE,
+        };
+        Ok(HistoryFile { path, size })
+    }
+
+    /// Reads the history into the editor. A missing file is fine, as it just means that
+    /// nothing has been saved yet.
+    pub fn load<H: Helper>(&self, editor: &mut Editor<H>) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        match editor.load_history(path) {
+            Ok(()) => {}
+            Err(ReadlineError::Io(err)) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => eprintln!("Couldn't read the history from {}: {err}", path.display()),
+        }
+    }
+
+    /// Writes the history of the editor to the file, creating its directory if needed
+    pub fn save<H: Helper>(&self, editor: &mut Editor<H>) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = match path.parent() {
+            Some(dir) => std::fs::create_dir_all(dir).map_err(ReadlineError::Io),
+            None => Ok(()),
+        };
+        if let Err(err) = result.and_then(|_| editor.save_history(path)) {
+            eprintln!("Couldn't save the history to {}: {err}", path.display());
+        }
+    }
+
+    /// Forgets the history of the editor, and empties the file right away
+    pub fn clear<H: Helper>(&self, editor: &mut Editor<H>) {
+        editor.clear_history();
+        let Some(path) = &self.path else {
+            return;
+        };
+        // rustyline doesn't write an empty history, so the file is truncated here
+        match OpenOptions::new().write(true).truncate(true).open(path) {
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => eprintln!("Couldn't clear the history in {}: {err}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod history_tests {
+    use super::*;
+    use rustyline::Config;
+
+    #[test]
+    fn options_come_first() {
+        let path = PathBuf::from("/tmp/history");
+        let history = HistoryFile::new(Some(path.clone()), Some(5)).unwrap();
+        assert_eq!(history, HistoryFile { path: Some(path), size: 5 });
+    }
+
+    #[test]
+    fn saved_and_loaded() {
+        let dir = std::env::temp_dir().join(format!("tiralabra-history-{}", std::process::id()));
+        let history = HistoryFile { path: Some(dir.join("sub").join("history")), size: 3 };
+        let config = Config::builder().max_history_size(history.size).build();
+
+        let mut editor = Editor::<()>::with_config(config);
+        for line in ["1", "2", "2", "3", "4"] {
+            editor.add_history_entry(line);
+        }
+        history.save(&mut editor);
+
+        let mut editor = Editor::<()>::with_config(config);
+        history.load(&mut editor);
+        let lines: Vec<&String> = editor.history().iter().collect();
+        assert_eq!(lines, ["2", "3", "4"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cleared() {
+        let path = std::env::temp_dir().join(format!("tiralabra-cleared-{}", std::process::id()));
+        let history = HistoryFile { path: Some(path.clone()), size: 3 };
+        let mut editor = Editor::<()>::new();
+        editor.add_history_entry("1 + 1");
+        history.save(&mut editor);
+        assert!(std::fs::read_to_string(&path).unwrap().contains("1 + 1"));
+
+        history.clear(&mut editor);
+        assert!(editor.history().is_empty());
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("1 + 1"));
+
+        let mut editor = Editor::<()>::new();
+        history.load(&mut editor);
+        assert!(editor.history().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn missing_file_is_empty_history() {
+        let history = HistoryFile { path: Some(PathBuf::from("/nonexistent/history")), size: 3 };
+        let mut editor = Editor::<()>::new();
+        history.load(&mut editor);
+        assert!(editor.history().is_empty());
+    }
+}
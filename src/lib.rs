@@ -0,0 +1,26 @@
+//! A calculator for infix expressions like `(1 + 2) * 3`, using Dijkstra's shunting yard
+//! algorithm.
+//!
+//! An expression goes through three steps:
+//! `tokenize` turns the input into `Token`s, `shunting_yard` reorders them into postfix
+//! notation, and `Calculator` evaluates the postfix expression. `Calculator` does all of them
+//! at once, and also keeps track of variables, user-defined functions and earlier results.
+//!
+//! ```
+//! use tiralabra_calculator::Calculator;
+//!
+//! let mut calculator = Calculator::new();
+//! calculator.calculate_infix("f(x) = x^2 + 1").unwrap();
+//!
+//! assert_eq!(calculator.evaluate("f(3) * 2").unwrap(), 20.0);
+//! assert_eq!(calculator.calculate_infix("ans / 4").unwrap(), "5");
+//! ```
+
+mod logic;
+
+pub use logic::{
+    implicit_multiplication, implicit_multiplication_spanned, shunting_yard, shunting_yard_spanned,
+    tokenize, tokenize_spanned, AngleMode, CalcError, Calculator, Format, Function,
+    ImplicitMultiplication, Notation, NumberMode, Operator, PercentMode, Radix, RationalDisplay,
+    Span, Token, Value, CONSTANTS,
+};
@@ -1,5 +1,5 @@
 mod calculator;
-pub use calculator::Calculator;
+pub use calculator::{Calculator, NumberMode, PercentMode};
 
 mod shunting_yard;
 mod tokenize;
@@ -8,7 +8,25 @@
 // `use ...::shunting_yard;`
 // instead of
 // `use ...::shunting_yard::shunting_yard`;
-use shunting_yard::shunting_yard;
-use tokenize::tokenize;
+pub use shunting_yard::{shunting_yard, shunting_yard_spanned};
+pub use tokenize::{tokenize, tokenize_spanned};
 
 mod enums;
+pub use enums::{AngleMode, Function, Operator, Token};
+
+mod error;
+pub use error::{CalcError, Span};
+
+mod constants;
+pub use constants::CONSTANTS;
+
+mod value;
+pub use value::Value;
+
+mod implicit;
+pub use implicit::{
+    implicit_multiplication, implicit_multiplication_spanned, ImplicitMultiplication,
+};
+
+mod format;
+pub use format::{Format, Notation, Radix, RationalDisplay};
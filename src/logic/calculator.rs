@@ -1,14 +1,34 @@
-use super::shunting_yard;
-use super::tokenize;
+use super::shunting_yard_spanned;
+use super::tokenize_spanned;
+use super::implicit::{implicit_multiplication_spanned, ImplicitMultiplication};
+use super::constants::get_constant;
+use super::error::{CalcError, Span};
+use std::cmp::Ordering;
+use std::f64::consts::PI;
+use std::cell::Cell;
 use std::collections::HashMap;
-use super::enums::Token::{self, Op, Variable, Number};
+use std::fmt;
+use std::str::FromStr;
+use super::enums::Token::{self, Op, Variable, Number, History, ImaginaryUnit, JumpIfFalse, Jump};
+use super::enums::{gamma, truth_value, AngleMode};
 use super::enums::Operator::{self, *};
+use super::format::{complex_to_string, Format, RationalDisplay};
+use super::value::Value;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive, Zero};
 
 /// Struct for keeping track of history and variables, and performing calculations.
 ///
+/// Every successful result is stored in the history. The latest one can be used in later
+/// expressions as `ans` (or `_`), and any earlier one by its number, e.g. `$3` for the third.
+///
 /// Example:
 ///
 /// ```
+/// use tiralabra_calculator::Calculator;
+///
 /// let mut calculator = Calculator::new();
 /// let result = calculator.calculate_infix("(1 + 2) * 3").unwrap();
 ///
@@ -17,342 +37,2233 @@
 ///
 /// With variables:
 /// ```
+/// use tiralabra_calculator::Calculator;
+///
 /// let mut calculator = Calculator::new();
 ///
 /// // returns a's value, in this case 1
 /// let result = calculator.calculate_infix("a = 1").unwrap();
 /// assert_eq!(result, "1");
 ///
-/// let result = calulator.calculator_infix("a + 1").unwrap();
+/// let result = calculator.calculate_infix("a + 1").unwrap();
 /// assert_eq!(result, "2");
 /// ```
 ///
+/// In exact mode, fractions don't lose precision:
+/// ```
+/// use tiralabra_calculator::{Calculator, NumberMode};
+///
+/// let mut calculator = Calculator::new();
+/// calculator.set_number_mode(NumberMode::Exact);
+///
+/// assert_eq!(calculator.calculate_infix("1/3 * 3").unwrap(), "1");
+/// assert_eq!(calculator.calculate_infix("0.1 + 0.2").unwrap(), "3/10");
+/// ```
+///
 pub struct Calculator {
-    variables: HashMap<String, f64>,
+    variables: HashMap<String, Value>,
+    functions: HashMap<String, UserFunction>,
+    history: Vec<Value>,
+    number_mode: NumberMode,
+    percent_mode: PercentMode,
+    implicit_multiplication: ImplicitMultiplication,
+    angle_mode: AngleMode,
+    format: Format,
+}
+
+/// How numbers are represented while calculating
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberMode {
+    /// Integers are exact, but any other number is a 64-bit float. This is the default
+    Float,
+    /// `+`, `-`, `*`, `/` and integer powers are calculated with exact fractions.
+    /// Other operations, like most functions, still use floats
+    Exact,
+}
+
+/// The mode as it's written in the settings: `float` or `exact`
+impl fmt::Display for NumberMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            NumberMode::Float => "float",
+            NumberMode::Exact => "exact",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for NumberMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "float" => Ok(NumberMode::Float),
+            "exact" => Ok(NumberMode::Exact),
+            _ => Err("Expected float or exact".to_string()),
+        }
+    }
+}
+
+/// What a percentage means after `+` or `-`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PercentMode {
+    /// `%` only divides by 100, so `50 + 10%` is 50.1. This is the default
+    Plain,
+    /// Like on a pocket calculator, adding or subtracting a percentage is relative to the left
+    /// operand, so `50 + 10%` is 55 and `50 - 10%` is 45
+    Pocket,
+}
+
+/// The mode as it's written in the settings: `plain` or `pocket`
+impl fmt::Display for PercentMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            PercentMode::Plain => "plain",
+            PercentMode::Pocket => "pocket",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for PercentMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(PercentMode::Plain),
+            "pocket" => Ok(PercentMode::Pocket),
+            _ => Err("Expected plain or pocket".to_string()),
+        }
+    }
+}
+
+/// A function defined by the user, e.g. `f(x, y) = x^2 + y`
+struct UserFunction {
+    params: Vec<String>,
+    /// The body of the function, already converted to postfix
+    body: Vec<(Token, Span)>,
+    /// How the function was defined, e.g. "f(x) = x^2"
+    definition: String,
+    /// The implicit multiplication mode the body was read in, so that `save` can write the
+    /// definition back in the same mode
+    implicit_multiplication: ImplicitMultiplication,
+}
+
+/// What a successfully executed line of input produced
+enum Outcome {
+    /// The value of an expression (which may also have been stored in a variable)
+    Value(Value),
+    /// The definition of a new function, e.g. "f(x) = x^2"
+    Definition(String),
+}
+
+/// How many user-defined function calls can be nested, so that e.g. `f(x) = f(x)` returns
+/// an error instead of overflowing the stack
+const MAX_RECURSION_DEPTH: usize = 128;
+
+/// The largest number of bits (roughly) an exact power is allowed to have. Anything larger is
+/// calculated with floats instead, so that e.g. `10^(10^9)` doesn't run out of memory (it's
+/// an error, as it doesn't fit in a float either)
+const MAX_EXACT_POW_BITS: u64 = 1 << 20;
+
+/// How much work evaluating an expression is allowed to do
+#[derive(Debug)]
+struct Limits {
+    /// How many user-defined function calls can be nested
+    recursion_depth: usize,
+    /// The largest number of bits an exact power or shift is allowed to have. A power between
+    /// this and `MAX_EXACT_POW_BITS` is an error instead of being calculated with floats
+    exact_pow_bits: u64,
+    /// The largest number whose factorial is calculated
+    factorial: u64,
+    /// How many more tokens can be evaluated (counting the ones in function bodies), or
+    /// `None` for no limit
+    steps: Option<Cell<usize>>,
+}
+
+impl Limits {
+    /// The limits of an ordinary calculation
+    fn full() -> Limits {
+        Limits {
+            recursion_depth: MAX_RECURSION_DEPTH,
+            exact_pow_bits: MAX_EXACT_POW_BITS,
+            factorial: MAX_EXACT_FACTORIAL,
+            steps: None,
+        }
+    }
+
+    /// Much lower limits for `Calculator::preview`, which is called on every keystroke and
+    /// has to return immediately. Something like `50000!` is simply not previewed.
+    fn preview() -> Limits {
+        Limits {
+            recursion_depth: 16,
+            exact_pow_bits: 1 << 14,
+            factorial: 1000,
+            steps: Some(Cell::new(100_000)),
+        }
+    }
+
+    /// Uses up one step, or returns an error if there are none left
+    fn step(&self) -> Result<(), CalcError> {
+        match &self.steps {
+            Some(steps) if steps.get() == 0 => Err(CalcError::Domain {
+                message: "The calculation takes too many steps".to_string(),
+                span: Span::default(),
+            }),
+            Some(steps) => {
+                steps.set(steps.get() - 1);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for Calculator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl Calculator {
+impl Calculator {
+
+    /// Creates a new `Calculator` object and initializes its variable table.
+    pub fn new() -> Calculator {
+        Calculator {
+            variables: HashMap::new(),
+            functions: HashMap::new(),
+            history: Vec::new(),
+            number_mode: NumberMode::Float,
+            percent_mode: PercentMode::Plain,
+            implicit_multiplication: ImplicitMultiplication::Normal,
+            angle_mode: AngleMode::Radians,
+            format: Format::default(),
+        }
+    }
+
+    /// Returns all results calculated so far, oldest first.
+    /// `$1` refers to the first item, `$2` to the second, and so on.
+    pub fn history(&self) -> &[Value] {
+        &self.history
+    }
+
+    /// Returns the variables defined so far with their values, sorted by name
+    pub fn variables(&self) -> Vec<(&str, &Value)> {
+        let mut variables: Vec<_> = self
+            .variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+            .collect();
+        variables.sort_by_key(|&(name, _)| name);
+        variables
+    }
+
+    /// Returns the definitions of the functions defined so far, e.g. "f(x) = x^2", sorted by
+    /// the name of the function
+    pub fn functions(&self) -> Vec<&str> {
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by_key(|&(name, _)| name);
+        functions.into_iter().map(|(_, function)| function.definition.as_str()).collect()
+    }
+
+    /// Removes the variable or the function called `name`. Returns false if there was neither.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let variable = self.variables.remove(name).is_some();
+        let function = self.functions.remove(name).is_some();
+        variable || function
+    }
+
+    /// Forgets the results calculated so far, so that the next one is `$1` again
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Removes all variables and functions. The history and the settings are kept.
+    pub fn clear(&mut self) {
+        self.variables.clear();
+        self.functions.clear();
+    }
+
+    /// Returns the variables, the functions and the settings as text that `load` can read.
+    ///
+    /// Every line is also valid input for the REPL, so a saved session can be replayed by
+    /// pasting it: the settings are written as `?` commands (e.g. `?deg`), the variables as
+    /// assignments and the functions as definitions. The history isn't saved.
+    ///
+    /// ```
+    /// use tiralabra_calculator::{AngleMode, Calculator};
+    ///
+    /// let mut calculator = Calculator::new();
+    /// calculator.set_angle_mode(AngleMode::Degrees);
+    /// calculator.calculate_infix("a = 2").unwrap();
+    /// calculator.calculate_infix("f(x) = a * x").unwrap();
+    ///
+    /// let session = calculator.save();
+    /// assert!(session.contains("?deg\n"));
+    /// assert!(session.contains("a = 2\n"));
+    /// assert!(session.contains("?implicit normal\nf(x) = a * x\n"));
+    ///
+    /// let mut restored = Calculator::new();
+    /// restored.load(&session).unwrap();
+    /// assert_eq!(restored.calculate_infix("f(3)").unwrap(), "6");
+    /// assert_eq!(restored.angle_mode(), AngleMode::Degrees);
+    /// ```
+    pub fn save(&self) -> String {
+        let mut lines = vec![
+            format!("?format {}", self.format),
+            format!("?{}", self.format.rational),
+            format!("?{}", self.angle_mode),
+            format!("?{}", self.percent_mode),
+        ];
+
+        // floats are only read back as floats in float mode, and fractions as fractions in
+        // exact mode, so the fractions are assigned separately after switching to exact mode
+        let (fractions, others): (Vec<_>, Vec<_>) = self
+            .variables()
+            .into_iter()
+            .partition(|(_, value)| matches!(value, Value::Rational(_)));
+        let assignment = |(name, value): (&str, &Value)| format!("{name} = {}", literal(value));
+        lines.push(format!("?{}", NumberMode::Float));
+        lines.extend(others.into_iter().map(assignment));
+        if !fractions.is_empty() {
+            lines.push(format!("?{}", NumberMode::Exact));
+            lines.extend(fractions.iter().copied().map(assignment));
+        }
+
+        // `1/2x` means something else in tight mode, so every definition is written in the mode
+        // it was made in, switching modes only when needed
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by_key(|&(name, _)| name);
+        let mut mode = None;
+        for (_, function) in functions {
+            if mode != Some(function.implicit_multiplication) {
+                lines.push(format!("?implicit {}", function.implicit_multiplication));
+                mode = Some(function.implicit_multiplication);
+            }
+            lines.push(function.definition.clone());
+        }
+        if mode != Some(self.implicit_multiplication) {
+            lines.push(format!("?implicit {}", self.implicit_multiplication));
+        }
+
+        // the mode is float after the variables, or exact if there were fractions
+        if (self.number_mode == NumberMode::Exact) == fractions.is_empty() {
+            lines.push(format!("?{}", self.number_mode));
+        }
+        lines.iter().map(|line| format!("{line}\n")).collect()
+    }
+
+    /// Replaces the variables, the functions and the settings with the ones in `session`,
+    /// which is text written by `save`. The history is kept.
+    ///
+    /// Each line is either a setting (like in the REPL, e.g. `?deg`), an assignment, a function
+    /// definition or empty. If a line can't be read, nothing is changed, and the error is
+    /// returned with the number of the line, counting from 1.
+    pub fn load(&mut self, session: &str) -> Result<(), (usize, CalcError)> {
+        let mut loaded = Calculator::new();
+        for (i, line) in session.lines().enumerate() {
+            let result = match line.strip_prefix('?') {
+                Some(setting) => loaded.apply_setting(setting).map_err(|message| {
+                    CalcError::Syntax { message, span: 0..line.len() }
+                }),
+                None if line.trim().is_empty() => Ok(()),
+                None => loaded.execute(line, true).map(drop),
+            };
+            result.map_err(|err| (i + 1, err))?;
+        }
+        loaded.history = std::mem::take(&mut self.history);
+        *self = loaded;
+        Ok(())
+    }
+
+    /// Changes a setting written the same way as the REPL's command for it, without the `?`,
+    /// e.g. "deg", "implicit tight" or "format sci 3". `save` writes the settings with the
+    /// `Display` of the modes, and this reads them back with `FromStr`.
+    ///
+    /// ```
+    /// use tiralabra_calculator::{AngleMode, Calculator};
+    ///
+    /// let mut calculator = Calculator::new();
+    /// calculator.apply_setting("deg").unwrap();
+    /// assert_eq!(calculator.angle_mode(), AngleMode::Degrees);
+    /// assert!(calculator.apply_setting("implicit sometimes").is_err());
+    /// ```
+    pub fn apply_setting(&mut self, setting: &str) -> Result<(), String> {
+        let setting = setting.trim();
+        let (name, args) = setting.split_once(' ').unwrap_or((setting, ""));
+        match (name, args.trim()) {
+            ("format", args) => self.format.update(args)?,
+            ("implicit", mode) => self.implicit_multiplication = mode.parse()?,
+            (name, "") => {
+                if let Ok(mode) = name.parse() {
+                    self.number_mode = mode;
+                } else if let Ok(display) = name.parse() {
+                    self.format.rational = display;
+                } else if let Ok(mode) = name.parse() {
+                    self.angle_mode = mode;
+                } else if let Ok(mode) = name.parse() {
+                    self.percent_mode = mode;
+                } else {
+                    return Err(format!("Unknown setting: ?{setting}"));
+                }
+            }
+            _ => return Err(format!("Unknown setting: ?{setting}")),
+        }
+        Ok(())
+    }
+
+    pub fn number_mode(&self) -> NumberMode {
+        self.number_mode
+    }
+
+    /// Changes how later calculations are done. Already stored values are converted when
+    /// they are used.
+    pub fn set_number_mode(&mut self, mode: NumberMode) {
+        self.number_mode = mode;
+    }
+
+    pub fn percent_mode(&self) -> PercentMode {
+        self.percent_mode
+    }
+
+    pub fn set_percent_mode(&mut self, mode: PercentMode) {
+        self.percent_mode = mode;
+    }
+
+    pub fn implicit_multiplication(&self) -> ImplicitMultiplication {
+        self.implicit_multiplication
+    }
+
+    /// Changes whether `2x` means `2 * x`, see `ImplicitMultiplication`. Functions that are
+    /// already defined keep the meaning they were defined with.
+    pub fn set_implicit_multiplication(&mut self, mode: ImplicitMultiplication) {
+        self.implicit_multiplication = mode;
+    }
+
+    pub fn angle_mode(&self) -> AngleMode {
+        self.angle_mode
+    }
+
+    /// Changes the unit of angles in the trigonometric functions and their inverses
+    pub fn set_angle_mode(&mut self, mode: AngleMode) {
+        self.angle_mode = mode;
+    }
+
+    pub fn rational_display(&self) -> RationalDisplay {
+        self.format.rational
+    }
+
+    pub fn set_rational_display(&mut self, display: RationalDisplay) {
+        self.format.rational = display;
+    }
+
+    /// The settings `calculate_infix` formats its results with
+    pub fn format(&self) -> &Format {
+        &self.format
+    }
+
+    pub fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+
+    /// Formats a value the way `calculate_infix` shows its results
+    ///
+    /// ```
+    /// use tiralabra_calculator::{Calculator, Value};
+    ///
+    /// let mut calculator = Calculator::new();
+    /// let third = calculator.evaluate_value("1 / 3").unwrap();
+    /// assert_eq!(calculator.format_value(&Value::from(1)), "1");
+    /// assert_eq!(calculator.format_value(&third), "0.3333333333333333");
+    /// ```
+    pub fn format_value(&self, value: &Value) -> String {
+        self.format.apply(value)
+    }
+
+    /// Enter a string with an infix expression (example: "2 * (2 + 1)") as parameter.
+    /// Returns a result containing the evaluated result of the expression, or an error
+    ///
+    /// If the expression starts with `name =`, the result is also stored in a variable.
+    /// If it starts with e.g. `f(x, y) =`, a function is defined instead, and the definition
+    /// is returned rather than a value.
+    ///
+    /// The spans of the errors refer to byte positions in `input`.
+    ///
+    /// ```
+    /// use tiralabra_calculator::Calculator;
+    ///
+    /// let mut calculator = Calculator::new();
+    /// let res = calculator.calculate_infix("1 + 6 / 3").unwrap();
+    /// assert_eq!(res, "3");
+    ///
+    /// let res = calculator.calculate_infix("f(x) = 2 * x").unwrap();
+    /// assert_eq!(res, "f(x) = 2 * x");
+    /// ```
+    pub fn calculate_infix(&mut self, input: &str) -> Result<String, CalcError> {
+        let format = self.format;
+        self.calculate_infix_with(input, &format)
+    }
+
+    /// Same as `calculate_infix`, but the result is formatted with `format` instead of the
+    /// calculator's own settings.
+    ///
+    /// ```
+    /// use tiralabra_calculator::{Calculator, Format, Notation};
+    ///
+    /// let mut calculator = Calculator::new();
+    /// let format = Format { notation: Notation::Fixed(3), ..Format::default() };
+    /// assert_eq!(calculator.calculate_infix_with("2 / 3", &format).unwrap(), "0.667");
+    /// assert_eq!(calculator.calculate_infix("ans").unwrap(), "0.6666666666666666");
+    /// ```
+    pub fn calculate_infix_with(
+        &mut self,
+        input: &str,
+        format: &Format,
+    ) -> Result<String, CalcError> {
+        match self.execute(input, true)? {
+            Outcome::Value(result) => Ok(format.apply(&result)),
+            Outcome::Definition(definition) => Ok(definition),
+        }
+    }
+
+    /// Same as `calculate_infix`, but returns the result as a number instead of a string.
+    ///
+    /// Variables can be assigned to, but as a function definition has no value, defining
+    /// functions returns an error. So does a complex result, although it's still stored in the
+    /// history.
+    ///
+    /// ```
+    /// use tiralabra_calculator::Calculator;
+    ///
+    /// let mut calculator = Calculator::new();
+    /// assert_eq!(calculator.evaluate("a = 2 ^ 3").unwrap(), 8.0);
+    /// assert_eq!(calculator.evaluate("a / 16").unwrap(), 0.5);
+    ///
+    /// assert!(calculator.evaluate("f(x) = x").is_err());
+    /// ```
+    pub fn evaluate(&mut self, input: &str) -> Result<f64, CalcError> {
+        match self.evaluate_value(input)? {
+            Value::Complex(c) => Err(CalcError::Domain {
+                message: format!("The result is a complex number: {}", Value::Complex(c)),
+                span: 0..input.len(),
+            }),
+            result => Ok(result.to_f64()),
+        }
+    }
+
+    /// Same as `evaluate`, but returns the result as a `Value`, which is exact in exact mode.
+    pub fn evaluate_value(&mut self, input: &str) -> Result<Value, CalcError> {
+        match self.execute(input, false)? {
+            Outcome::Value(result) => Ok(result),
+            Outcome::Definition(_) => unreachable!("definitions are not allowed"),
+        }
+    }
+
+    /// Calculates `input` like `evaluate_value`, but without changing anything: nothing is
+    /// stored in the history, and an assignment like `a = 1 + 2` only calculates its right side.
+    /// Used for showing the result while the input is still being typed, so anything that
+    /// would take long (e.g. `50000!` or deep recursion) is an error instead.
+    ///
+    /// ```
+    /// use tiralabra_calculator::{Calculator, Value};
+    ///
+    /// let calculator = Calculator::new();
+    /// assert_eq!(calculator.preview("a = 1 + 2").unwrap(), Value::from(3));
+    /// assert!(calculator.history().is_empty());
+    /// ```
+    pub fn preview(&self, input: &str) -> Result<Value, CalcError> {
+        let tokens = tokenize_spanned(input)?;
+        let mut tokens = implicit_multiplication_spanned(tokens, self.implicit_multiplication);
+
+        if let Some(i) = tokens.iter().position(|(token, _)| *token == Op(Equals)) {
+            let expression = tokens.split_off(i + 1);
+            if let Some((Op(Func(..) | UserFunc(..)), span)) = tokens.first() {
+                let message = "A function definition has no value";
+                return Err(CalcError::syntax(message, span.start..tokens[i].1.end));
+            }
+            tokens = expression;
+        }
+
+        let postfix = shunting_yard_spanned(tokens)?;
+        self.eval_in_scope(&postfix, &HashMap::new(), 0, &Limits::preview())
+    }
+
+    /// Calculates, assigns or defines whatever `input` says, and stores the result in the
+    /// history. `allow_definitions` tells whether defining functions is possible.
+    fn execute(&mut self, input: &str, allow_definitions: bool) -> Result<Outcome, CalcError> {
+        let tokens = tokenize_spanned(input)?;
+        let mut tokens = implicit_multiplication_spanned(tokens, self.implicit_multiplication);
+
+        // if the input contains a '=', split the tokens into two parts
+        let (target, tokens) = match tokens.iter().position(|(token, _)| *token == Op(Equals)) {
+            Some(i) => {
+                let expression = tokens.split_off(i + 1);
+                let (_, equals_span) = tokens.pop().unwrap();
+                (Some((tokens, equals_span)), expression)
+            }
+            None => (None, tokens),
+        };
+
+        if let Some((target, equals_span)) = &target {
+            // if expression starts with =
+            // (e.g. "= 1 + 1")
+            if target.is_empty() {
+                return Err(CalcError::syntax("Variable required before '='", equals_span.clone()));
+            }
+
+            // e.g. "f(x) = x^2"
+            if let (Op(Func(..) | UserFunc(..)), span) = &target[0] {
+                if !allow_definitions {
+                    let message = "A function definition has no value";
+                    return Err(CalcError::syntax(message, span.start..equals_span.end));
+                }
+                let source = input[equals_span.end..].trim();
+                return self.define_function(target, tokens, source).map(Outcome::Definition);
+            }
+
+            // if expression has more than one token before =
+            // (e.g. "a b = 1 + 1")
+            if target.len() > 1 {
+                let span = target[1].1.start..target[target.len() - 1].1.end;
+                return Err(CalcError::syntax("Too many tokens before '='", span));
+            }
+        }
+
+        let postfix = shunting_yard_spanned(tokens)?;
+        let result = self.eval_postfix(&postfix)?;
+
+        // if the expression is supposed to assign to a variable,
+        // insert the key-value pair into `variables`
+        if let Some((target, _)) = target {
+            // Get the first (only) item from the list and insert it into `self.variables`
+            // with the corresponding value
+            match &target[0] {
+                (Variable(variable), span) => {
+                    if get_constant(variable).is_some() {
+                        return Err(CalcError::ReadOnly {
+                            message: format!("Cannot assign to a constant: {variable}"),
+                            span: span.clone(),
+                        });
+                    }
+                    if is_previous_result(variable) {
+                        return Err(CalcError::ReadOnly {
+                            message: format!("Cannot assign to {variable}, it holds the previous result"),
+                            span: span.clone(),
+                        });
+                    }
+                    self.variables.insert(variable.to_string(), result.clone());
+                }
+                (_, span) => {
+                    return Err(CalcError::syntax("Malformed input before '='", span.clone()));
+                }
+            }
+        }
+        self.history.push(result.clone());
+        Ok(Outcome::Value(result))
+    }
+
+    /// Stores a user-defined function. `target` is everything before the '=' (e.g. `f(x, y)`),
+    /// `body` everything after it. The body isn't evaluated until the function is called.
+    ///
+    /// Returns the definition, e.g. "f(x, y) = x + y".
+    fn define_function(
+        &mut self,
+        target: &[(Token, Span)],
+        body: Vec<(Token, Span)>,
+        source: &str,
+    ) -> Result<String, CalcError> {
+        // for errors about something missing from the end of the target
+        let target_end = target[target.len() - 1].1.end;
+
+        let mut target = target.iter();
+        let name = match target.next() {
+            Some((Op(UserFunc(name, _)), _)) => name.to_string(),
+            Some((Op(Func(fun, _)), span)) => {
+                return Err(CalcError::ReadOnly {
+                    message: format!("Cannot redefine a built-in function: {}", fun.format()),
+                    span: span.clone(),
+                });
+            }
+            _ => unreachable!("define_function is only called for function calls"),
+        };
+
+        match target.next() {
+            Some((Op(Lparen), _)) => {}
+            Some((_, span)) => {
+                return Err(CalcError::syntax("Expected '(' after the function name", span.clone()));
+            }
+            None => {
+                let span = target_end..target_end;
+                return Err(CalcError::syntax("Expected '(' after the function name", span));
+            }
+        }
+
+        // parameter names separated by commas, e.g. "x, y)". "f() = 1" has no parameters
+        let mut params: Vec<String> = Vec::new();
+        loop {
+            match target.next() {
+                Some((Variable(param), span)) => {
+                    if params.contains(param) {
+                        let message = format!("Duplicate parameter: {param}");
+                        return Err(CalcError::syntax(message, span.clone()));
+                    }
+                    params.push(param.to_string());
+                }
+                Some((Op(Rparen), _)) if params.is_empty() => break,
+                Some((_, span)) => {
+                    return Err(CalcError::syntax("Parameters have to be variable names", span.clone()));
+                }
+                None => {
+                    let span = target_end..target_end;
+                    return Err(CalcError::syntax("Parameters have to be variable names", span));
+                }
+            }
+
+            match target.next() {
+                Some((Op(Comma), _)) => continue,
+                Some((Op(Rparen), _)) => break,
+                other => {
+                    let span = other.map_or(target_end..target_end, |(_, span)| span.clone());
+                    return Err(CalcError::syntax("Expected ',' or ')' after a parameter", span));
+                }
+            }
+        }
+
+        if let Some((_, span)) = target.next() {
+            let span = span.start..target_end;
+            return Err(CalcError::syntax("Too many tokens before '='", span));
+        }
+
+        let body = shunting_yard_spanned(body)?;
+        if body.is_empty() {
+            let span = target_end..target_end;
+            return Err(CalcError::syntax("Function body is empty", span));
+        }
+
+        let definition = format!("{name}({}) = {source}", params.join(", "));
+        let function = UserFunction {
+            params,
+            body,
+            definition: definition.clone(),
+            implicit_multiplication: self.implicit_multiplication,
+        };
+        self.functions.insert(name, function);
+        Ok(definition)
+    }
+
+    /// Calculates a postfix expression and returns a single numerical value. (Or an error if the
+    /// expression is malformed)
+    ///
+    /// ```text
+    /// "1 + 1" -> [(Number(1.0.into()), 0..1), (Number(1.0.into()), 4..5), (Op(Plus), 2..3)] -> 2.0
+    /// ```
+    fn eval_postfix(&self, input: &[(Token, Span)]) -> Result<Value, CalcError> {
+        self.eval_in_scope(input, &HashMap::new(), 0, &Limits::full())
+    }
+
+    /// Same as `eval_postfix`, but variables are looked up from `locals` (the parameters of
+    /// a user-defined function) before anything else. `depth` is the number of user-defined
+    /// function calls currently in progress, and `limits` bounds the work done.
+    fn eval_in_scope(
+        &self,
+        input: &[(Token, Span)],
+        locals: &HashMap<String, Value>,
+        depth: usize,
+        limits: &Limits,
+    ) -> Result<Value, CalcError> {
+        // every value on the stack remembers which part of the input it was calculated from
+        let mut stack: Vec<(Value, Span)> = Vec::new();
+        // an index instead of an iterator, as `if` jumps around in the expression
+        let mut i = 0;
+        // the token evaluated before the current one, to find `x + 10%` in `PercentMode::Pocket`
+        let mut previous = None;
+        while let Some((token, span)) = input.get(i) {
+            i += 1;
+            let span = span.clone();
+            limits.step().map_err(|err| err.with_span(span.clone()))?;
+            let after_percent = previous == Some(&Op(Percent));
+            previous = Some(token);
+            match token {
+                Number(num) => stack.push((self.convert(num.clone()), span)),
+                Op(Neg) => {
+                    let (a, a_span) = pop_value(&mut stack, &span)?;
+                    stack.push((-a, span.start..a_span.end));
+                }
+                Op(Pos) => {
+                    let (a, a_span) = pop_value(&mut stack, &span)?;
+                    stack.push((a, span.start..a_span.end));
+                }
+                Op(Not) => {
+                    let (a, a_span) = pop_value(&mut stack, &span)?;
+                    let span = span.start..a_span.end;
+                    let truth = truth_value(&a).map_err(|err| err.with_span(span.clone()))?;
+                    stack.push((Value::from_bool(!truth), span));
+                }
+                &JumpIfFalse(target) => {
+                    let (condition, condition_span) = pop_value(&mut stack, &span)?;
+                    if !truth_value(&condition).map_err(|err| err.with_span(condition_span))? {
+                        i = target;
+                    }
+                }
+                &Jump(target) => i = target,
+                Op(Factorial) => {
+                    let (a, a_span) = pop_value(&mut stack, &span)?;
+                    let span = a_span.start..span.end;
+                    let result = factorial(&a, limits).map_err(|err| err.with_span(span.clone()))?;
+                    stack.push((self.convert(result), span));
+                }
+                Op(Degree) => {
+                    let (a, a_span) = pop_value(&mut stack, &span)?;
+                    let span = a_span.start..span.end;
+                    let result = self
+                        .degrees_to_mode(a)
+                        .map_err(|err| err.with_span(span.clone()))?;
+                    stack.push((self.convert(result), span));
+                }
+                Op(Percent) => {
+                    let (a, a_span) = pop_value(&mut stack, &span)?;
+                    let span = a_span.start..span.end;
+                    let result = operate_values(a, Value::from(100), Div, limits)
+                        .map_err(|err| err.with_span(span.clone()))?;
+                    stack.push((self.convert(result), span));
+                }
+                &Op(Func(fun, arg_count)) => {
+                    let args = pop_args(&mut stack, arg_count, &span)?;
+                    let result = fun
+                        .apply_in(&args, self.angle_mode)
+                        .map_err(|err| err.with_span(span.clone()))?;
+                    stack.push((self.convert(result), span));
+                }
+                Op(UserFunc(name, arg_count)) => {
+                    let args = pop_args(&mut stack, *arg_count, &span)?;
+                    let result = self
+                        .call_function(name, args, depth, limits)
+                        .map_err(|err| err.with_span(span.clone()))?;
+                    stack.push((result, span));
+                }
+                Op(op) => {
+                    let (a, a_span) = pop_value(&mut stack, &span)?;
+                    let (b, b_span) = pop_value(&mut stack, &span)?;
+                    let whole_span = b_span.start..a_span.end;
+                    // "50 + 10%" is 50 + 50 * 10%
+                    let pocket = self.percent_mode == PercentMode::Pocket && after_percent;
+                    let a = if pocket && matches!(op, Plus | Minus) {
+                        let a = operate_values(b.clone(), a, Mul, limits);
+                        self.convert(a.map_err(|err| err.with_span(whole_span.clone()))?)
+                    } else {
+                        a
+                    };
+                    let result = operate_values(b, a, op.clone(), limits).map_err(|err| match err {
+                        // point at the divisor
+                        CalcError::DivisionByZero { .. } => err.with_span(a_span),
+                        _ => err.with_span(whole_span.clone()),
+                    })?;
+                    stack.push((self.convert(result), whole_span));
+                }
+                Variable(var) => {
+                    // parameters shadow everything else inside a function.
+                    // Otherwise constants take priority over (and can't be shadowed by) variables
+                    let value = if let Some(val) = locals.get(var) {
+                        val.clone()
+                    } else if let Some(val) = get_constant(var) {
+                        val
+                    } else if is_previous_result(var) {
+                        self.history.last().cloned().ok_or_else(|| CalcError::MissingResult {
+                            message: "No previous result".to_string(),
+                            span: span.clone(),
+                        })?
+                    } else if let Some(val) = self.variables.get(var) {
+                        val.clone()
+                    } else {
+                        return Err(CalcError::UndefinedVariable { name: var.to_string(), span });
+                    };
+                    stack.push((self.convert(value), span));
+                }
+                ImaginaryUnit => stack.push((Value::from(Complex64::i()), span)),
+                &History(n) => {
+                    // results are numbered from 1, so $0 doesn't exist
+                    match n.checked_sub(1).and_then(|i| self.history.get(i)) {
+                        Some(val) => stack.push((self.convert(val.clone()), span)),
+                        None => {
+                            return Err(CalcError::MissingResult {
+                                message: format!("No result ${n} in history"),
+                                span,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        let (res, _) = stack
+            .pop()
+            .ok_or_else(|| CalcError::syntax("Too many operators", Span::default()))?;
+
+        if let Some((_, span)) = stack.pop() {
+            return Err(CalcError::syntax("Too many numbers!", span));
+        }
+        Ok(res)
+    }
+
+    /// Calls the user-defined function `name`, binding `args` to its parameters.
+    ///
+    /// The spans of the returned errors are meaningless, as the function body comes from a
+    /// different input; the caller should replace them with the span of the call.
+    fn call_function(
+        &self,
+        name: &str,
+        args: Vec<Value>,
+        depth: usize,
+        limits: &Limits,
+    ) -> Result<Value, CalcError> {
+        let function = self
+            .functions
+            .get(name)
+            .ok_or_else(|| CalcError::UnknownFunction { name: name.to_string(), span: Span::default() })?;
+
+        let expected = function.params.len();
+        if args.len() != expected {
+            let plural = if expected == 1 { "" } else { "s" };
+            return Err(CalcError::Arity {
+                message: format!("{name} expects {expected} argument{plural}, got {}", args.len()),
+                span: Span::default(),
+            });
+        }
+
+        if depth >= limits.recursion_depth {
+            return Err(CalcError::Recursion { name: name.to_string(), span: Span::default() });
+        }
+
+        let locals = function
+            .params
+            .iter()
+            .cloned()
+            .zip(args)
+            .collect();
+        self.eval_in_scope(&function.body, &locals, depth + 1, limits)
+    }
+
+    /// Converts an angle in degrees to the current `AngleMode`. Degrees to gradians stays exact
+    fn degrees_to_mode(&self, angle: Value) -> Result<Value, CalcError> {
+        match self.angle_mode {
+            AngleMode::Degrees => Ok(angle),
+            AngleMode::Gradians => {
+                let ratio = BigRational::new(BigInt::from(10), BigInt::from(9));
+                operate_values(angle, Value::from(ratio), Mul, &Limits::full())
+            }
+            AngleMode::Radians => {
+                operate_values(angle, Value::Float(PI / 180.0), Mul, &Limits::full())
+            }
+        }
+    }
+
+    /// Converts a value to the representation used in the current `NumberMode`
+    fn convert(&self, value: Value) -> Value {
+        match value {
+            Value::Rational(_) if self.number_mode == NumberMode::Float => value.to_float(),
+            _ => value,
+        }
+    }
+}
+
+/// Pops one value for the operator at `span`
+fn pop_value(stack: &mut Vec<(Value, Span)>, span: &Span) -> Result<(Value, Span), CalcError> {
+    stack
+        .pop()
+        .ok_or_else(|| CalcError::syntax("Too many operators", span.clone()))
+}
+
+/// Pops the `count` topmost values as the arguments of the function called at `span`
+fn pop_args(stack: &mut Vec<(Value, Span)>, count: usize, span: &Span) -> Result<Vec<Value>, CalcError> {
+    if stack.len() < count {
+        return Err(CalcError::syntax("Too few numbers", span.clone()));
+    }
+    let args = stack.split_off(stack.len() - count);
+    Ok(args.into_iter().map(|(value, _)| value).collect())
+}
+
+/// Applies a binary operator to two values. If both are exact, so is the result (when
+/// possible), otherwise the operation is done with floats using `operate`.
+fn operate_values(a: Value, b: Value, op: Operator, limits: &Limits) -> Result<Value, CalcError> {
+    // only the precedence is different
+    let op = if op == ImplicitMul { Mul } else { op };
+    if matches!(op, BitAnd | BitOr | BitXor | Shl | Shr) {
+        return operate_bitwise(&a, &b, op, limits);
+    }
+    if matches!(op, Less | Greater | LessEqual | GreaterEqual | EqualTo | NotEqual | And | Or) {
+        return operate_logical(&a, &b, op);
+    }
+    // e.g. (-8)^(1/3) has no real value
+    let complex_power = op == Pow && a.to_f64() < 0.0 && b.to_integer().is_none();
+    if a.is_complex() || b.is_complex() || complex_power {
+        return operate_complex(a.to_complex(), b.to_complex(), op);
+    }
+    match (a.to_rational(), b.to_rational()) {
+        (Some(a), Some(b)) => operate_exact(a, b, op, limits),
+        _ => operate(a.to_f64(), b.to_f64(), op).map(Value::Float),
+    }
+}
+
+/// Same as `operate`, but with complex numbers
+fn operate_complex(a: Complex64, b: Complex64, op: Operator) -> Result<Value, CalcError> {
+    if a.is_nan() || b.is_nan() || a.is_infinite() || b.is_infinite() {
+        return Err(CalcError::Domain {
+            message: "At least one argument is not a finite number".to_string(),
+            span: Span::default(),
+        });
+    }
+    let res = match op {
+        Plus => a + b,
+        Minus => a - b,
+        Mul => a * b,
+        Div if b.is_zero() => return Err(CalcError::DivisionByZero { span: Span::default() }),
+        Div => a / b,
+        // `powc` would turn e.g. i^2 into -1 + 0.00000000000000012i
+        Pow if b.im == 0.0 && b.re.fract() == 0.0 && b.re.abs() <= i32::MAX as f64 => {
+            if a.is_zero() && b.re < 0.0 {
+                return Err(CalcError::DivisionByZero { span: Span::default() });
+            }
+            a.powi(b.re as i32)
+        }
+        Pow => a.powc(b),
+        _ => {
+            return Err(CalcError::Domain {
+                message: format!("'{op}' is not defined for complex numbers"),
+                span: Span::default(),
+            });
+        }
+    };
+    Ok(Value::from(res))
+}
+
+/// Applies a bitwise operator (`&`, `|`, `xor`, `<<`, `>>`). Both operands have to be
+/// integers. Negative numbers behave as if they were in two's complement with infinitely
+/// many bits, so e.g. `-1 & 6` is 6.
+fn operate_bitwise(
+    a: &Value,
+    b: &Value,
+    op: Operator,
+    limits: &Limits,
+) -> Result<Value, CalcError> {
+    let (a, b) = match (a.to_integer(), b.to_integer()) {
+        (Some(a), Some(b)) => (a, b),
+        _ => {
+            return Err(CalcError::Domain {
+                message: format!("The operands of '{op}' have to be integers"),
+                span: Span::default(),
+            });
+        }
+    };
+    if matches!(op, Shl | Shr) && b.is_negative() {
+        return Err(CalcError::Domain {
+            message: "Cannot shift by a negative amount".to_string(),
+            span: Span::default(),
+        });
+    }
+    let res = match op {
+        BitAnd => &a & &b,
+        BitOr => &a | &b,
+        BitXor => &a ^ &b,
+        Shl => {
+            let shift = b
+                .to_u64()
+                .filter(|&shift| a.bits().saturating_add(shift) <= limits.exact_pow_bits)
+                .ok_or_else(|| CalcError::Domain {
+                    message: "The result of the shift would be too large".to_string(),
+                    span: Span::default(),
+                })?;
+            &a << shift
+        }
+        // shifting right by more bits than fit in memory always gives 0 (or -1)
+        Shr => match b.to_u64() {
+            Some(shift) => &a >> shift,
+            None if a.is_negative() => BigInt::from(-1),
+            None => BigInt::from(0),
+        },
+        _ => unreachable!("only called for bitwise operators"),
+    };
+    Ok(Value::Int(res))
+}
+
+/// The largest number whose factorial is calculated. Its factorial has about 700 000 bits,
+/// a bit less than `MAX_EXACT_POW_BITS`
+const MAX_EXACT_FACTORIAL: u64 = 50_000;
+
+/// `n!` for non-negative integers up to `limits.factorial`. For other real numbers it's
+/// `gamma(n + 1)`, which is calculated with floats.
+fn factorial(value: &Value, limits: &Limits) -> Result<Value, CalcError> {
+    let domain_error = |message: &str| CalcError::Domain {
+        message: message.to_string(),
+        span: Span::default(),
+    };
+    if value.is_complex() {
+        return Err(domain_error("The factorial is not defined for complex numbers"));
+    }
+    match value.to_integer() {
+        Some(n) if n.is_negative() => {
+            Err(domain_error("The factorial is not defined for negative integers"))
+        }
+        Some(n) => {
+            let n = n
+                .to_u64()
+                .filter(|&n| n <= limits.factorial)
+                .ok_or_else(|| domain_error("The factorial is too large"))?;
+            Ok(Value::Int((1..=n).map(BigInt::from).product()))
+        }
+        None => {
+            let res = gamma(value.to_f64() + 1.0);
+            if res.is_finite() {
+                Ok(Value::Float(res))
+            } else {
+                Err(domain_error("The factorial is too large"))
+            }
+        }
+    }
+}
+
+/// Applies a comparison or a logical operator. The result is 1 for true and 0 for false.
+///
+/// Both operands of `and` and `or` have already been evaluated, use `if` to avoid evaluating
+/// something. Comparisons with NaN are false, except for `!=`, and complex numbers can't be
+/// ordered.
+fn operate_logical(a: &Value, b: &Value, op: Operator) -> Result<Value, CalcError> {
+    let ordering = a.partial_cmp(b);
+    let ordered = matches!(op, Less | Greater | LessEqual | GreaterEqual);
+    if ordered && (a.is_complex() || b.is_complex()) {
+        return Err(CalcError::Domain {
+            message: format!("Complex numbers can't be compared with '{op}'"),
+            span: Span::default(),
+        });
+    }
+    let res = match op {
+        Less => ordering == Some(Ordering::Less),
+        Greater => ordering == Some(Ordering::Greater),
+        LessEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        GreaterEqual => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        EqualTo => ordering == Some(Ordering::Equal),
+        NotEqual => ordering != Some(Ordering::Equal),
+        And => truth_value(a)? && truth_value(b)?,
+        Or => truth_value(a)? || truth_value(b)?,
+        _ => unreachable!("only called for comparisons and logical operators"),
+    };
+    Ok(Value::from_bool(res))
+}
+
+/// Same as `operate`, but with exact fractions.
+///
+/// A power is only exact if the exponent is an integer (and the result isn't absurdly large),
+/// otherwise it is calculated with floats.
+fn operate_exact(
+    a: BigRational,
+    b: BigRational,
+    op: Operator,
+    limits: &Limits,
+) -> Result<Value, CalcError> {
+    match op {
+        Plus => Ok((a + b).into()),
+        Minus => Ok((a - b).into()),
+        Mul => Ok((a * b).into()),
+        Div => {
+            if b.is_zero() {
+                Err(CalcError::DivisionByZero { span: Span::default() })
+            } else {
+                Ok((a / b).into())
+            }
+        }
+        FloorDiv | Mod if b.is_zero() => Err(CalcError::DivisionByZero { span: Span::default() }),
+        FloorDiv => Ok((a / b).floor().into()),
+        Mod => {
+            let quotient = (&a / &b).floor();
+            Ok((a - b * quotient).into())
+        }
+        Pow => {
+            let exponent = if b.is_integer() { b.to_integer().to_i32() } else { None };
+            let size = a.numer().bits() + a.denom().bits();
+            match exponent {
+                Some(e) if size.saturating_mul(e.unsigned_abs() as u64) <= MAX_EXACT_POW_BITS => {
+                    if a.is_zero() && e.is_negative() {
+                        return Err(CalcError::DivisionByZero { span: Span::default() });
+                    }
+                    if size.saturating_mul(e.unsigned_abs() as u64) > limits.exact_pow_bits {
+                        return Err(CalcError::Domain {
+                            message: "The power is too large to calculate here".to_string(),
+                            span: Span::default(),
+                        });
+                    }
+                    Ok(a.pow(e).into())
+                }
+                _ => operate(a.to_f64().unwrap_or(f64::NAN), b.to_f64().unwrap_or(f64::NAN), Pow)
+                    .map(Value::Float),
+            }
+        }
+        _ => Err(CalcError::syntax(format!("Unrecognized operator: {op:?}"), Span::default())),
+    }
+}
+
+/// `ans` and `_` both refer to the latest result
+fn is_previous_result(name: &str) -> bool {
+    name == "ans" || name == "_"
+}
+
+/// Writes `value` as input that calculates the same value, for `Calculator::save`.
+/// Floats are written with all their digits (or as `inf`), unlike in `Value`'s `Display`, and
+/// inside `float()`, as e.g. `2.0` would be read back as the integer 2.
+///
+/// ```text
+/// Float(0.1 + 0.2) -> "float(0.30000000000000004)"
+/// Float(1e20)      -> "float(1e20)"
+/// Rational(-1/3)   -> "-1/3"
+/// ```
+fn literal(value: &Value) -> String {
+    let float = |x: f64| format!("{x:?}");
+    match value {
+        Value::Int(_) | Value::Rational(_) => value.to_string(),
+        Value::Float(x) => format!("float({})", float(*x)),
+        Value::Complex(c) => complex_to_string(c.re, c.im, float),
+    }
+}
+
+/// Operate on the argument values depending on the `c` character.
+///
+/// The first argument, `a`, is the one the operation is applied to,
+/// for example 
+/// ```text
+/// operate(8.0, 2.0, Div)
+/// ```
+/// divides 8.0 by 2.0, not the other way.
+///
+/// If either `a` or `b` is NaN or inf, return an error (because it's not obvious how the
+/// application should behave in those cases)
+///
+/// If dividing by zero or trying to use an unrecognized operator, an error is also returned.
+///
+/// The errors have an empty span, as `operate` doesn't know where its arguments came from.
+///
+fn operate(a: f64, b: f64, op: Operator) -> Result<f64, CalcError> {
+    use crate::logic::enums::Operator::*;
+    // neither a or b should ever be NaN or infinite (should be caught beforehand), 
+    // but in case it happens anyway, return an error
+    if a.is_nan() || b.is_nan() {
+        return Err(CalcError::Domain {
+            message: "At least one argument is not a number (NaN)".to_string(),
+            span: Span::default(),
+        });
+    }
+    if a.is_infinite() || b.is_infinite() {
+        return Err(CalcError::Domain {
+            message: "At least one argument is infinite".to_string(),
+            span: Span::default(),
+        });
+    }
+
+    match op {
+        Plus => Ok(a + b),
+        Minus => Ok(a - b),
+        Mul => Ok(a * b),
+        Div => {
+            if b == 0.0 {
+                Err(CalcError::DivisionByZero { span: Span::default() })
+            } else {
+                Ok(a / b)
+            }
+        }
+        FloorDiv | Mod if b == 0.0 => Err(CalcError::DivisionByZero { span: Span::default() }),
+        FloorDiv => Ok((a / b).floor()),
+        Mod => {
+            // `%` of floats has the sign of `a`, it should have the sign of `b`
+            let remainder = a % b;
+            if remainder != 0.0 && (remainder < 0.0) != (b < 0.0) {
+                Ok(remainder + b)
+            } else {
+                Ok(remainder)
+            }
+        }
+        // a power of finite numbers is only infinite if it's too large for a float, or if it's
+        // a negative power of zero
+        Pow if a == 0.0 && b < 0.0 => Err(CalcError::DivisionByZero { span: Span::default() }),
+        Pow => {
+            let res = a.powf(b);
+            if res.is_infinite() {
+                return Err(CalcError::Domain {
+                    message: "The result of the power is too large".to_string(),
+                    span: Span::default(),
+                });
+            }
+            Ok(res)
+        }
+        // should not be reached ever, but in case of error elsewhere,
+        // this branch will catch it
+        _ => Err(CalcError::syntax(format!("Unrecognized operator: {op:?}"), Span::default())),
+    }
+}
+
+
+#[cfg(test)]
+mod eval_postfix_tests {
+    use super::*;
+
+    /// The spans don't matter in these tests
+    fn spanned(tokens: Vec<Token>) -> Vec<(Token, Span)> {
+        tokens.into_iter().map(|token| (token, Span::default())).collect()
+    }
+
+    #[test]
+    fn single_digit_works() {
+        let calculator = Calculator::new();
+        let test_vec = vec![Number(1.0.into())];
+        let res = calculator.eval_postfix(&spanned(test_vec)).unwrap();
+
+        assert_eq!(res, 1.0);
+    }
+
+    #[test]
+    fn one_one_plus_works() {
+        let calculator = Calculator::new();
+        let test_vec = vec![Number(1.0.into()), Number(1.0.into()), Op(Plus)];
+        let res = calculator.eval_postfix(&spanned(test_vec)).unwrap();
+
+        assert_eq!(res, 2.0);
+    }
+
+    #[test]
+    fn three_two_mul_four_plus_works() {
+        let calculator = Calculator::new();
+        let test_vec = vec![
+            Number(3.0.into()),
+            Number(2.0.into()),
+            Op(Mul),
+            Number(4.0.into()),
+            Op(Plus)
+        ];
+        let res = calculator.eval_postfix(&spanned(test_vec)).unwrap();
+
+        assert_eq!(res, 10.0);
+    }
+
+    #[test]
+    fn operator_before_numbers_returns_error() {
+        let calculator = Calculator::new();
+        let test_vec = vec![Op(Plus), Number(1.0.into()), Number(2.0.into())];
+        let res = calculator.eval_postfix(&spanned(test_vec));
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn too_many_operators_returns_error() {
+        let calculator = Calculator::new();
+        let test_vec = vec![Number(1.0.into()), Number(1.0.into()), Op(Mul), Op(Plus)];
+
+        let res = calculator.eval_postfix(&spanned(test_vec));
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn too_many_numbers_returns_error() {
+        let calculator = Calculator::new();
+        let test_vec = vec![Number(1.0.into()), Number(1.0.into()), Op(Div), Number(1.0.into())];
+        let res = calculator.eval_postfix(&spanned(test_vec));
+
+        assert!(res.is_err());
+    }
+}
+
+#[cfg(test)]
+mod operate_tests {
+    use super::operate;
+    use super::Operator::*;
+
+    #[test]
+    fn one_plus_one_is_two() {
+        let res = operate(1.0, 1.0, Plus);
+
+        assert_eq!(res.unwrap(), 2.0);
+    }
+
+    #[test]
+    fn two_times_four_is_eight() {
+        let res = operate(2.0, 4.0, Mul);
+
+        assert_eq!(res.unwrap(), 8.0);
+    }
+
+    #[test]
+    fn four_div_eight_is_half() {
+        let res = operate(4.0, 8.0, Div);
+
+        assert_eq!(res.unwrap(), 0.5);
+    }
+
+    #[test]
+    fn one_minus_two_is_minus_one() {
+        let res = operate(1.0, 2.0, Minus);
+
+        assert_eq!(res.unwrap(), -1.0);
+    }
+
+    #[test]
+    fn unknown_operator_returns_err() {
+        let res = operate(1.0, 10.0, Rparen);
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn divide_by_zero_returns_nan() {
+        let res = operate(1.0, 0.0, Div);
+
+        assert!(res.is_err());
+    }
+}
+
+#[cfg(test)]
+mod calculate_infix_tests {
+    use super::{Calculator, ImplicitMultiplication, NumberMode, PercentMode, RationalDisplay};
+    use crate::logic::enums::AngleMode;
+    use crate::logic::format::Format;
+    use crate::logic::error::CalcError;
+    use crate::logic::value::Value;
+    use num_rational::BigRational;
+
+    #[test]
+    fn input_only_operator_doesnt_panic() {
+        let mut calculator = Calculator::new();
+        let res = calculator.calculate_infix("+");
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn parentheses_work() {
+        let mut calculator = Calculator::new();
+        let res = calculator.calculate_infix("(1 + 2) * 3");
+        assert_eq!(res.unwrap(), "9");
+    }
+
+    #[test]
+    fn error_when_extra_before_equals() {
+        let mut calculator = Calculator::new();
+        let res = calculator.calculate_infix("a b = 1");
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn error_equals_without_variable() {
+        let mut calculator = Calculator::new();
+        let res = calculator.calculate_infix("= 1 + 2");
+
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn correct_variables_stored_and_returned() {
+        let mut calculator = Calculator::new();
+        let res = calculator.calculate_infix("a = 1 + 2");
+        assert_eq!(res.unwrap(), "3");
+
+        let res = calculator.calculate_infix("b = 1 + a");
+        assert_eq!(res.unwrap(), "4");
+
+        let res = calculator.calculate_infix("b + a + a");
+        assert_eq!(res.unwrap(), "10");
+    }
+
+    #[test]
+    fn operator_chains_are_grouped_correctly() {
+        let cases = [
+            ("8 - 3 - 2", 3.0),
+            ("8 / 4 / 2", 1.0),
+            ("2 ^ 3 ^ 2", 512.0),
+            ("8 + 4 - 2", 8.0 + 4.0 - 2.0),
+            ("8 - 4 + 2", 8.0 - 4.0 + 2.0),
+            ("8 * 4 / 2", 8.0 * 4.0 / 2.0),
+            ("8 / 4 * 2", 8.0 / 4.0 * 2.0),
+            ("8 - 4 * 2", 8.0 - 4.0 * 2.0),
+            ("8 / 4 - 2", 8.0 / 4.0 - 2.0),
+            ("8 - 4 ^ 2", 8.0 - 4.0_f64.powf(2.0)),
+            ("8 ^ 4 - 2", 8.0_f64.powf(4.0) - 2.0),
+            ("8 / 4 ^ 2", 8.0 / 4.0_f64.powf(2.0)),
+            ("8 ^ 4 / 2", 8.0_f64.powf(4.0) / 2.0),
+            ("20 - 8 - 4 - 2", 6.0),
+            ("64 / 8 / 4 / 2", 1.0),
+        ];
+
+        let mut calculator = Calculator::new();
+        for (input, correct) in cases {
+            let res = calculator.calculate_infix(input).unwrap();
+            assert_eq!(res, format!("{correct}"), "{input}");
+        }
+    }
+
+    #[test]
+    fn unary_signs_work() {
+        let mut calculator = Calculator::new();
+        calculator.calculate_infix("x = 3").unwrap();
+
+        let cases = [
+            ("-x", "-3"),
+            ("-(1 + 2)", "-3"),
+            ("-sin(0) + 1", "1"),
+            ("2 * -x", "-6"),
+            ("-2^2", "-4"),
+            ("2^-1", "0.5"),
+            ("--1", "1"),
+            ("+x - +1", "2"),
+            ("max(1, -x)", "1"),
+        ];
+        for (input, correct) in cases {
+            let res = calculator.calculate_infix(input).unwrap();
+            assert_eq!(res, correct, "{input}");
+        }
+    }
+
+    #[test]
+    fn constants_can_be_used() {
+        let mut calculator = Calculator::new();
+        let res = calculator.calculate_infix("2 * pi - tau");
+        assert_eq!(res.unwrap(), "0");
+
+        let res = calculator.calculate_infix("log(e ^ 2, e)");
+        assert_eq!(res.unwrap(), "2");
+    }
+
+    #[test]
+    fn constants_are_read_only() {
+        let mut calculator = Calculator::new();
+        let res = calculator.calculate_infix("pi = 3");
+        assert_eq!(res.unwrap_err().to_string(), "Cannot assign to a constant: pi");
+
+        let res = calculator.calculate_infix("pi");
+        assert_eq!(res.unwrap(), format!("{}", std::f64::consts::PI));
+    }
+
+    #[test]
+    fn previous_results_can_be_used() {
+        let mut calculator = Calculator::new();
+        let res = calculator.calculate_infix("ans");
+        assert_eq!(res.unwrap_err().to_string(), "No previous result");
+
+        calculator.calculate_infix("1 + 2").unwrap();
+        let res = calculator.calculate_infix("ans * 2");
+        assert_eq!(res.unwrap(), "6");
+
+        let res = calculator.calculate_infix("_ + 1");
+        assert_eq!(res.unwrap(), "7");
+
+        let res = calculator.calculate_infix("$1 + $2");
+        assert_eq!(res.unwrap(), "9");
+
+        assert_eq!(calculator.history(), &[3.0, 6.0, 7.0, 9.0]);
+    }
+
+    #[test]
+    fn failed_calculations_are_not_in_history() {
+        let mut calculator = Calculator::new();
+        calculator.calculate_infix("1").unwrap();
+        assert!(calculator.calculate_infix("1 / 0").is_err());
+
+        assert_eq!(calculator.history(), &[1.0]);
+        let res = calculator.calculate_infix("$2");
+        assert_eq!(res.unwrap_err().to_string(), "No result $2 in history");
+        let res = calculator.calculate_infix("$0");
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn previous_result_is_read_only() {
+        let mut calculator = Calculator::new();
+        assert!(calculator.calculate_infix("ans = 1").is_err());
+        assert!(calculator.calculate_infix("_ = 1").is_err());
+    }
+
+    #[test]
+    fn user_functions_can_be_defined_and_called() {
+        let mut calculator = Calculator::new();
+        let res = calculator.calculate_infix("f(x) = x^2 + 1");
+        assert_eq!(res.unwrap(), "f(x) = x^2 + 1");
+
+        let res = calculator.calculate_infix("f(3)");
+        assert_eq!(res.unwrap(), "10");
+
+        let res = calculator.calculate_infix("g(a, b) = f(a) * b");
+        assert_eq!(res.unwrap(), "g(a, b) = f(a) * b");
+
+        let res = calculator.calculate_infix("g(1, 2) + f(-1)");
+        assert_eq!(res.unwrap(), "6");
+
+        let res = calculator.calculate_infix("one() = 1");
+        assert_eq!(res.unwrap(), "one() = 1");
+        assert_eq!(calculator.calculate_infix("one() + 1").unwrap(), "2");
+    }
+
+    #[test]
+    fn function_parameters_are_local() {
+        let mut calculator = Calculator::new();
+        calculator.calculate_infix("x = 10").unwrap();
+        calculator.calculate_infix("y = 100").unwrap();
+        calculator.calculate_infix("f(x) = x + y").unwrap();
+
+        let res = calculator.calculate_infix("f(1)");
+        assert_eq!(res.unwrap(), "101");
+
+        let res = calculator.calculate_infix("x");
+        assert_eq!(res.unwrap(), "10");
+
+        // global variables are read when the function is called, not when it is defined
+        calculator.calculate_infix("y = 200").unwrap();
+        let res = calculator.calculate_infix("f(x)");
+        assert_eq!(res.unwrap(), "210");
+    }
+
+    #[test]
+    fn infinite_recursion_errors() {
+        let mut calculator = Calculator::new();
+        calculator.calculate_infix("f(x) = f(x)").unwrap();
+
+        let res = calculator.calculate_infix("f(1)");
+        assert_eq!(res.unwrap_err().to_string(), "Maximum recursion depth exceeded when calling f");
+    }
+
+    #[test]
+    fn preview_has_lower_limits() {
+        let mut calculator = Calculator::new();
+        calculator.calculate_infix("f(n) = if(n < 1, 0, f(n - 1))").unwrap();
+
+        assert_eq!(calculator.preview("10!").unwrap(), Value::from(3628800));
+        assert!(calculator.preview("50000!").is_err());
+        assert!(calculator.preview("2^100000").is_err());
+        assert!(calculator.preview("1 << 100000").is_err());
+        assert!(calculator.preview("f(100)").is_err());
+        assert_eq!(calculator.calculate_infix("f(100)").unwrap(), "0");
+        assert_eq!(calculator.calculate_infix("2^100000 > 10^30000").unwrap(), "1");
+    }
+
+    #[test]
+    fn preview_has_a_step_budget() {
+        let mut calculator = Calculator::new();
+        let definition = "f(x) = if(x < 1, 1, f(x - 1) + f(x - 1) + f(x - 1) + f(x - 1))";
+        calculator.calculate_infix(definition).unwrap();
+
+        assert_eq!(calculator.preview("f(3)").unwrap(), Value::from(64));
+        let start = std::time::Instant::now();
+        let err = calculator.preview("f(12)").unwrap_err();
+        assert_eq!(err.to_string(), "The calculation takes too many steps");
+        assert!(start.elapsed().as_secs() < 2);
+        assert_eq!(calculator.calculate_infix("f(5)").unwrap(), "1024");
+    }
+
+    #[test]
+    fn bad_function_definitions_error() {
+        let mut calculator = Calculator::new();
+        let inputs = [
+            "sin(x) = x",
+            "f(x, x) = x",
+            "f(1) = 1",
+            "f(x y) = x",
+            "f(x) y = x",
+            "f(x) =",
+            "f(x) = x +",
+        ];
+        for input in inputs {
+            assert!(calculator.calculate_infix(input).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn calling_user_functions_wrong_errors() {
+        let mut calculator = Calculator::new();
+        let res = calculator.calculate_infix("f(1)");
+        assert_eq!(res.unwrap_err().to_string(), "Unknown function: f");
+
+        calculator.calculate_infix("f(x, y) = x + y").unwrap();
+        let res = calculator.calculate_infix("f(1)");
+        assert_eq!(res.unwrap_err().to_string(), "f expects 2 arguments, got 1");
+    }
+
+    #[test]
+    fn evaluate_returns_numbers() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.evaluate("a = 1 / 4").unwrap(), 0.25);
+        assert_eq!(calculator.evaluate("a * 2 + ans").unwrap(), 0.75);
+        assert_eq!(calculator.history(), &[0.25, 0.75]);
+    }
+
+    #[test]
+    fn evaluate_rejects_function_definitions() {
+        let mut calculator = Calculator::new();
+        assert!(calculator.evaluate("f(x) = x").is_err());
+        assert!(calculator.evaluate("f(1)").is_err());
+    }
+
+    #[test]
+    fn multi_argument_functions_work() {
+        let mut calculator = Calculator::new();
+        let res = calculator.calculate_infix("min(4, 2 * 3, 3) + max(1, -2)");
+        assert_eq!(res.unwrap(), "4");
+
+        let res = calculator.calculate_infix("hypot(3, 4) * log(100)");
+        assert_eq!(res.unwrap(), "10");
+    }
+
+    #[test]
+    fn wrong_argument_count_errors() {
+        let mut calculator = Calculator::new();
+        let res = calculator.calculate_infix("min()");
+        assert_eq!(res.unwrap_err().to_string(), "min expects at least 1 argument, got 0");
 
-    /// Creates a new `Calculator` object and initializes its variable table.
-    pub fn new() -> Calculator {
-        Calculator {
-            variables: HashMap::new(),
-        }
+        let res = calculator.calculate_infix("atan2(1)");
+        assert_eq!(res.unwrap_err().to_string(), "atan2 expects 2 arguments, got 1");
     }
 
-    /// Enter a string with an infix expression (example: "2 * (2 + 1)") as parameter.
-    /// Returns a result containing the evaluated result of the expression, or an error
-    ///
-    /// ```
-    /// let mut calculator = Calculator::new();
-    /// let res = calculator.calculate_infix("1 + 6 / 3").unwrap();
-    ///
-    /// assert_eq!(res, "3");
-    /// ```
-    pub fn calculate_infix(&mut self, input: &str) -> Result<String, String> {
+    #[test]
+    fn error_bad_variable_input() {
+        let mut calculator = Calculator::new();
+        let res = calculator.calculate_infix("* = 1 + 2");
+        assert!(res.is_err());
+    }
 
-        let mut eq_position = None;
+    #[test]
+    fn errors_point_at_the_input() {
+        let mut calculator = Calculator::new();
 
-        // find the position of the '=' character, if it exists
-        for (i, c) in input.chars().enumerate() {
-            if c == '=' {
-                eq_position = Some(i);
-                break;
-            }
-        }
+        let err = calculator.calculate_infix("1 + foo").unwrap_err();
+        assert_eq!(err, CalcError::UndefinedVariable { name: "foo".to_string(), span: 4..7 });
 
-        // if the input string contains a '=', split it into two parts
-        let (variable, input) = if let Some(i) = eq_position {
-            (Some(tokenize(&input[..i])?), &input[i + 1..])
-        } else {
-            (None, input)
-        };
-        let tokens = tokenize(input)?;
-        let postfix = shunting_yard(tokens)?;
-        let result = self.eval_postfix(postfix)?;
+        let err = calculator.calculate_infix("1 / (2 - 2)").unwrap_err();
+        assert_eq!(err, CalcError::DivisionByZero { span: 5..10 });
 
-        // if the expression is supposed to assign to a variable,
-        // insert the key-value pair into `variables`
-        if let Some(var_list) = variable {
-            // if expression has more than one token before =
-            // (e.g. "a b = 1 + 1")
-            if var_list.len() > 1 {
-                return Err("Too many tokens before '='".to_string());
-            }
-            // if expression starts with =
-            // (e.g. "= 1 + 1")
-            if var_list.is_empty() {
-                return Err("Variable required before '='".to_string());
-            }
+        let err = calculator.calculate_infix("pi = 3").unwrap_err();
+        assert!(matches!(err, CalcError::ReadOnly { span, .. } if span == (0..2)));
 
-            // Get the first (only) item from the list and insert it into `self.variables`
-            // with the corresponding value
-            if let Variable(variable) = &var_list[0] {
-                self.variables.insert(variable.to_string(), result);
-            } else {
-                return Err("Malformed input before '='".to_string());
-            }
-        }
-        Ok(format!("{result}"))
+        let err = calculator.calculate_infix("2 * min()").unwrap_err();
+        assert!(matches!(err, CalcError::Arity { span, .. } if span == (4..9)));
     }
 
-    /// Calculates a postfix expression and returns a single numerical value. (Or an error if the
-    /// expression is malformed)
-    ///
-    /// ```
-    /// let mut calculator = Calculator::new();
-    /// let tokens = vec![Number(1.0), Number(1.0), Op(Plus)];
-    /// let res = calculator.eval_postfix(tokens).unwrap();
-    /// 
-    /// assert_eq!(res, 2.0);
-    /// ```
-    fn eval_postfix(&self, input: Vec<Token>) -> Result<f64, String> {
-        let mut stack = Vec::new();
-        for token in input {
-            match token {
-                Number(num) => stack.push(num),
-                Op(Func(fun)) => {
-                    let arg = stack.pop().ok_or("Too few numbers")?;
-                    stack.push(fun.evaluate(arg));
-                }
-                Op(op) => {
-                    let a = stack.pop().ok_or("Too many operators")?;
-                    let b = stack.pop().ok_or("Too many operators")?;
-                    match operate(b, a, op) {
-                        Ok(result) => stack.push(result),
-                        err_msg @ Err(_) => return err_msg
-                    };
-
+    #[test]
+    fn errors_inside_user_functions_point_at_the_call() {
+        let mut calculator = Calculator::new();
+        calculator.calculate_infix("f(x) = x / 0").unwrap();
 
-                }
-                Variable(var) => {
-                    if let Some(&val) = self.variables.get(&var) {
-                        stack.push(val);
-                    } else {
-                        return Err(format!("Undefined variable: {var}"));
-                    }
-                }
-            }
-        }
+        let err = calculator.calculate_infix("1 + f(2)").unwrap_err();
+        assert_eq!(err, CalcError::DivisionByZero { span: 4..8 });
+    }
 
-        let res = stack.pop().ok_or("Too many operators")?;
+    #[test]
+    fn exact_mode_keeps_fractions() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.calculate_infix("0.1 + 0.2").unwrap(), "0.30000000000000004");
 
-        if !stack.is_empty() {
-            return Err("Too many numbers!".to_string());
-        }
-        Ok(res)
+        calculator.set_number_mode(NumberMode::Exact);
+        assert_eq!(calculator.calculate_infix("0.1 + 0.2").unwrap(), "3/10");
+        assert_eq!(calculator.calculate_infix("1/3 * 3").unwrap(), "1");
+        assert_eq!(calculator.calculate_infix("-(2/3)^-2").unwrap(), "-9/4");
+        assert_eq!(calculator.calculate_infix("2^100").unwrap(), "1267650600228229401496703205376");
     }
-}
 
-/// Operate on the argument values depending on the `c` character.
-///
-/// The first argument, `a`, is the one the operation is applied to,
-/// for example 
-/// ```
-/// operate(8.0, 2.0, '/')
-/// ```
-/// divides 8.0 by 2.0, not the other way.
-///
-/// If either `a` or `b` is NaN or inf, return an error (because it's not obvious how the
-/// application should behave in those cases)
-///
-/// If dividing by zero or trying to use an unrecognized operator, an error is also returned.
-///
-fn operate(a: f64, b: f64, op: Operator) -> Result<f64, String> {
-    use crate::logic::enums::Operator::*;
-    // neither a or b should ever be NaN or infinite (should be caught beforehand), 
-    // but in case it happens anyway, return an error
-    if a.is_nan() || b.is_nan() {
-        return Err("At least one argument is not a number (NaN)".to_string())
+    #[test]
+    fn exact_mode_falls_back_to_floats() {
+        let mut calculator = Calculator::new();
+        calculator.set_number_mode(NumberMode::Exact);
+        assert_eq!(calculator.calculate_infix("4^0.5").unwrap(), "2");
+        assert_eq!(calculator.evaluate_value("sqrt(4) + 1/2").unwrap(), Value::Float(2.5));
+        assert_eq!(calculator.evaluate_value("pi * 0").unwrap(), Value::Float(0.0));
     }
-    if a.is_infinite() || b.is_infinite() {
-        return Err("At least one argument is infinite".to_string());
+
+    #[test]
+    fn exact_mode_errors() {
+        let mut calculator = Calculator::new();
+        calculator.set_number_mode(NumberMode::Exact);
+        let err = calculator.calculate_infix("1 / (2 - 2)").unwrap_err();
+        assert_eq!(err, CalcError::DivisionByZero { span: 5..10 });
+        let err = calculator.calculate_infix("0 ^ -1").unwrap_err();
+        assert_eq!(err, CalcError::DivisionByZero { span: 4..6 });
     }
 
-    match op {
-        Plus => Ok(a + b),
-        Minus => Ok(a - b),
-        Mul => Ok(a * b),
-        Div => {
-            if b == 0.0 {
-                Err("Trying to divide by zero!".to_string())
-            } else {
-                Ok(a / b)
-            }
+    #[test]
+    fn too_large_powers_are_errors() {
+        let mut calculator = Calculator::new();
+        for input in ["10^(10^9)", "2^(2^40)", "sqrt(1e40)^20", "10^400.5"] {
+            let err = calculator.calculate_infix(input).unwrap_err();
+            assert_eq!(err.to_string(), "The result of the power is too large", "{input}");
         }
-        Pow => Ok(a.powf(b)),
-        // should not be reached ever, but in case of error elsewhere,
-        // this branch will catch it
-        _ => Err(format!("Unrecognized operator: {op:?}")),
+        // too small is zero, and not too large is still exact
+        assert_eq!(calculator.calculate_infix("10^(-10^9)").unwrap(), "0");
+        assert_eq!(calculator.calculate_infix("2^100").unwrap(), "1267650600228229401496703205376");
+        let err = calculator.calculate_infix("0^-1.5").unwrap_err();
+        assert_eq!(err, CalcError::DivisionByZero { span: 2..6 });
     }
-}
 
+    #[test]
+    fn switching_modes_converts_stored_values() {
+        let mut calculator = Calculator::new();
+        calculator.set_number_mode(NumberMode::Exact);
+        calculator.calculate_infix("a = 1/3").unwrap();
+        assert_eq!(calculator.history(), &[Value::from(BigRational::new(1.into(), 3.into()))]);
 
-#[cfg(test)]
-mod eval_postfix_tests {
-    use super::*;
+        calculator.set_rational_display(RationalDisplay::Decimal);
+        assert_eq!(calculator.calculate_infix("a").unwrap(), "0.3333333333333333");
+
+        calculator.set_number_mode(NumberMode::Float);
+        assert_eq!(calculator.evaluate_value("a * 3").unwrap(), Value::Float(1.0));
+    }
 
     #[test]
-    fn single_digit_works() {
-        let calculator = Calculator::new();
-        let test_vec = vec![Number(1.0)];
-        let res = calculator.eval_postfix(test_vec).unwrap();
+    fn integers_are_exact() {
+        let mut calculator = Calculator::new();
+        let res = calculator.calculate_infix("2^200").unwrap();
+        assert_eq!(res, "1606938044258990275541962092341162602522202993782792835301376");
+        let res = calculator.calculate_infix("ans - 2^200 + 1").unwrap();
+        assert_eq!(res, "1");
+        let res = calculator.calculate_infix("gcd(6^50 * 5, 6^50 * 7)").unwrap();
+        assert_eq!(res, "808281277464764060643139600456536293376");
+        assert_eq!(calculator.calculate_infix("12 / 4").unwrap(), "3");
+        assert!(matches!(calculator.history().last(), Some(Value::Int(_))));
+    }
 
-        assert_eq!(res, 1.0);
+    #[test]
+    fn integers_turn_into_floats_when_needed() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.evaluate_value("7 / 2").unwrap(), Value::Float(3.5));
+        assert_eq!(calculator.evaluate_value("2^-1").unwrap(), Value::Float(0.5));
+        assert_eq!(calculator.evaluate_value("sqrt(16)").unwrap(), Value::Float(4.0));
+        assert_eq!(calculator.evaluate_value("10^20 + 0.5").unwrap(), Value::Float(1e20));
     }
 
     #[test]
-    fn one_one_plus_works() {
-        let calculator = Calculator::new();
-        let test_vec = vec![Number(1.0), Number(1.0), Op(Plus)];
-        let res = calculator.eval_postfix(test_vec).unwrap();
+    fn complex_numbers() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.calculate_infix("sqrt(-1)").unwrap(), "i");
+        assert_eq!(calculator.calculate_infix("(3 + 4i) * (1 - 2i)").unwrap(), "11 - 2i");
+        assert_eq!(calculator.calculate_infix("i^2").unwrap(), "-1");
+        assert_eq!(calculator.calculate_infix("abs(3 + 4i)").unwrap(), "5");
+        assert_eq!(calculator.calculate_infix("z = 1 + i").unwrap(), "1 + i");
+        assert_eq!(calculator.calculate_infix("conj(z) / 2").unwrap(), "0.5 - 0.5i");
+        assert_eq!(calculator.calculate_infix("re(z) + im(z)").unwrap(), "2");
+        assert_eq!(calculator.calculate_infix("im((-4)^0.5)").unwrap(), "2");
+    }
 
-        assert_eq!(res, 2.0);
+    #[test]
+    fn imaginary_numbers_are_multiplications() {
+        // `2i` binds like `2x`, so the power is calculated first
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.calculate_infix("2i^2").unwrap(), "-2");
+        assert_eq!(calculator.calculate_infix("3 + 2i^2").unwrap(), "1");
+        assert_eq!(calculator.calculate_infix("1/2i").unwrap(), "0.5i");
+
+        calculator.set_implicit_multiplication(ImplicitMultiplication::Tight);
+        assert_eq!(calculator.calculate_infix("1/2i").unwrap(), "-0.5i");
+
+        calculator.set_implicit_multiplication(ImplicitMultiplication::Off);
+        assert_eq!(calculator.calculate_infix("3 + 4i").unwrap(), "3 + 4i");
+        // with a space it's an implicit multiplication, which is off
+        assert!(calculator.calculate_infix("4 i").is_err());
+        assert!(calculator.calculate_infix("4   i").is_err());
+        calculator.set_implicit_multiplication(ImplicitMultiplication::Normal);
+        assert_eq!(calculator.calculate_infix("4 i").unwrap(), "4i");
+
+        // a parameter called `i` doesn't change the meaning of `4i`
+        calculator.calculate_infix("f(i) = 4i + i").unwrap();
+        assert_eq!(calculator.calculate_infix("f(2)").unwrap(), "2 + 4i");
+
+        let err = calculator.calculate_infix("1e400i").unwrap_err();
+        assert!(matches!(err, CalcError::Lexical { .. }));
     }
 
     #[test]
-    fn three_two_mul_four_plus_works() {
-        let calculator = Calculator::new();
-        let test_vec = vec![
-            Number(3.0),
-            Number(2.0),
-            Op(Mul),
-            Number(4.0),
-            Op(Plus)
-        ];
-        let res = calculator.eval_postfix(test_vec).unwrap();
+    fn complex_errors() {
+        let mut calculator = Calculator::new();
+        let err = calculator.calculate_infix("i = 2").unwrap_err();
+        assert!(matches!(err, CalcError::ReadOnly { .. }));
 
-        assert_eq!(res, 10.0);
+        let err = calculator.calculate_infix("1 / (i - i)").unwrap_err();
+        assert_eq!(err, CalcError::DivisionByZero { span: 5..10 });
+
+        let err = calculator.calculate_infix("min(i, 2)").unwrap_err();
+        assert_eq!(err.to_string(), "min is not defined for complex numbers");
+
+        let err = calculator.evaluate("2i").unwrap_err();
+        assert_eq!(err.to_string(), "The result is a complex number: 2i");
     }
 
     #[test]
-    fn operator_before_numbers_returns_error() {
-        let calculator = Calculator::new();
-        let test_vec = vec![Op(Plus), Number(1.0), Number(2.0)];
-        let res = calculator.eval_postfix(test_vec);
+    fn results_are_formatted() {
+        let mut calculator = Calculator::new();
+        let mut format = Format::default();
+        format.update("sci 3").unwrap();
+        calculator.set_format(format);
+        assert_eq!(calculator.calculate_infix("2^100").unwrap(), "1.27e30");
 
-        assert!(res.is_err());
+        format.update("hex").unwrap();
+        assert_eq!(calculator.calculate_infix_with("ans / 2^96", &format).unwrap(), "0x10");
+        assert_eq!(calculator.calculate_infix("ans").unwrap(), "1.60e1");
+        assert_eq!(calculator.calculate_infix("f(x) = x").unwrap(), "f(x) = x");
     }
 
     #[test]
-    fn too_many_operators_returns_error() {
-        let calculator = Calculator::new();
-        let test_vec = vec![Number(1.0), Number(1.0), Op(Mul), Op(Plus)];
+    fn modulo_and_floor_division() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.calculate_infix("17 % 5 + 17 // 5").unwrap(), "5");
+        assert_eq!(calculator.calculate_infix("-7 % 3").unwrap(), "2");
+        assert_eq!(calculator.calculate_infix("7 % -3").unwrap(), "-2");
+        assert_eq!(calculator.calculate_infix("-7 // 2").unwrap(), "-4");
+        assert_eq!(calculator.calculate_infix("5.5 % 2").unwrap(), "1.5");
+        assert_eq!(calculator.calculate_infix("7.5 // 2").unwrap(), "3");
 
-        let res = calculator.eval_postfix(test_vec);
+        let err = calculator.calculate_infix("1 % (2 - 2)").unwrap_err();
+        assert_eq!(err, CalcError::DivisionByZero { span: 5..10 });
+    }
 
-        assert!(res.is_err());
+    #[test]
+    fn bitwise_operators() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.calculate_infix("0b1100 & 0b1010").unwrap(), "8");
+        assert_eq!(calculator.calculate_infix("0b1100 | 0b1010").unwrap(), "14");
+        assert_eq!(calculator.calculate_infix("0b1100 xor 0b1010").unwrap(), "6");
+        assert_eq!(calculator.calculate_infix("1 << 2 + 3").unwrap(), "32");
+        assert_eq!(calculator.calculate_infix("-17 >> 2").unwrap(), "-5");
+        assert_eq!(calculator.calculate_infix("-1 & 6").unwrap(), "6");
+        assert_eq!(calculator.calculate_infix("1 << 100 >> 99").unwrap(), "2");
+        assert_eq!(calculator.calculate_infix("4.0 | 1").unwrap(), "5");
     }
 
     #[test]
-    fn too_many_numbers_returns_error() {
-        let calculator = Calculator::new();
-        let test_vec = vec![Number(1.0), Number(1.0), Op(Div), Number(1.0)];
-        let res = calculator.eval_postfix(test_vec);
+    fn bitwise_operators_need_integers() {
+        let mut calculator = Calculator::new();
+        let err = calculator.calculate_infix("2.5 & 1").unwrap_err();
+        assert_eq!(err.to_string(), "The operands of '&' have to be integers");
+        assert_eq!(err.span(), &(0..7));
 
-        assert!(res.is_err());
+        let err = calculator.calculate_infix("1 << -1").unwrap_err();
+        assert_eq!(err.to_string(), "Cannot shift by a negative amount");
+        let err = calculator.calculate_infix("1 << 10^9").unwrap_err();
+        assert_eq!(err.to_string(), "The result of the shift would be too large");
+        assert!(calculator.calculate_infix("i | 1").is_err());
     }
-}
-
-#[cfg(test)]
-mod operate_tests {
-    use super::operate;
-    use super::Operator::*;
 
     #[test]
-    fn one_plus_one_is_two() {
-        let res = operate(1.0, 1.0, Plus);
+    fn comparisons_give_one_or_zero() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.calculate_infix("1 < 2").unwrap(), "1");
+        assert_eq!(calculator.calculate_infix("2 <= 1").unwrap(), "0");
+        assert_eq!(calculator.calculate_infix("0.5 + 0.25 == 0.75").unwrap(), "1");
+        assert_eq!(calculator.calculate_infix("1 / 3 != 0.333").unwrap(), "1");
+        assert_eq!(calculator.calculate_infix("2 + 2 >= 2 * 2").unwrap(), "1");
+        assert_eq!(calculator.calculate_infix("3 > 2 > 1").unwrap(), "0");
+        assert_eq!(calculator.calculate_infix("i == i").unwrap(), "1");
 
-        assert_eq!(res.unwrap(), 2.0);
+        let err = calculator.calculate_infix("i < 1").unwrap_err();
+        assert_eq!(err.to_string(), "Complex numbers can't be compared with '<'");
     }
 
     #[test]
-    fn two_times_four_is_eight() {
-        let res = operate(2.0, 4.0, Mul);
+    fn logical_operators() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.calculate_infix("1 < 2 and 2 < 3").unwrap(), "1");
+        assert_eq!(calculator.calculate_infix("1 > 2 or 0").unwrap(), "0");
+        assert_eq!(calculator.calculate_infix("not 0").unwrap(), "1");
+        assert_eq!(calculator.calculate_infix("not 5 - 5").unwrap(), "1");
+        assert_eq!(calculator.calculate_infix("not 1 or 1 and 0").unwrap(), "0");
+        assert_eq!(calculator.calculate_infix("not not 0.5").unwrap(), "1");
+        assert!(calculator.calculate_infix("1 not 2").is_err());
+    }
 
-        assert_eq!(res.unwrap(), 8.0);
+    #[test]
+    fn if_chooses_a_value() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.calculate_infix("if(1 < 2, 10, 20)").unwrap(), "10");
+        assert_eq!(calculator.calculate_infix("if(0, 10, 20) + 1").unwrap(), "21");
+        assert_eq!(calculator.calculate_infix("if(1, if(0, 1, 2), 3)").unwrap(), "2");
+        assert_eq!(calculator.calculate_infix("min(if(0, 1, 2), 5)").unwrap(), "2");
+
+        // the other value isn't evaluated at all
+        assert_eq!(calculator.calculate_infix("if(1, 2, 1 / 0)").unwrap(), "2");
+        assert_eq!(calculator.calculate_infix("if(0, undefined, 3)").unwrap(), "3");
     }
 
     #[test]
-    fn four_div_eight_is_half() {
-        let res = operate(4.0, 8.0, Div);
+    fn piecewise_functions_can_be_defined() {
+        let mut calculator = Calculator::new();
+        calculator.calculate_infix("abs2(x) = if(x < 0, -x, x)").unwrap();
+        assert_eq!(calculator.calculate_infix("abs2(-3)").unwrap(), "3");
+        assert_eq!(calculator.calculate_infix("abs2(4)").unwrap(), "4");
 
-        assert_eq!(res.unwrap(), 0.5);
+        // would recurse forever without short-circuiting
+        calculator.calculate_infix("fact(n) = if(n <= 1, 1, n * fact(n - 1))").unwrap();
+        assert_eq!(calculator.calculate_infix("fact(20)").unwrap(), "2432902008176640000");
     }
 
     #[test]
-    fn one_minus_two_is_minus_one() {
-        let res = operate(1.0, 2.0, Minus);
+    fn if_errors() {
+        let mut calculator = Calculator::new();
+        let err = calculator.calculate_infix("1 + if(1, 2)").unwrap_err();
+        assert_eq!(err.to_string(), "if expects 3 arguments, got 2");
+        assert_eq!(err.span(), &(4..12));
+        assert!(calculator.calculate_infix("if(1, 2, 3, 4)").is_err());
+        assert!(calculator.calculate_infix("if(1, 2, )").is_err());
+    }
 
-        assert_eq!(res.unwrap(), -1.0);
+    #[test]
+    fn factorials() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.calculate_infix("5!").unwrap(), "120");
+        assert_eq!(calculator.calculate_infix("0!").unwrap(), "1");
+        assert_eq!(calculator.calculate_infix("25!").unwrap(), "15511210043330985984000000");
+        assert_eq!(calculator.calculate_infix("-3!").unwrap(), "-6");
+        assert_eq!(calculator.calculate_infix("(1 + 2)! * 2").unwrap(), "12");
+        assert_eq!(calculator.calculate_infix("2^3!").unwrap(), "64");
+        assert_eq!(calculator.calculate_infix("3!!").unwrap(), "720");
+        assert_eq!(calculator.calculate_infix("3! != 6").unwrap(), "0");
+
+        // gamma(1.5) = sqrt(pi) / 2
+        let half = calculator.evaluate("0.5!").unwrap();
+        assert!((half - std::f64::consts::PI.sqrt() / 2.0).abs() < 1e-14);
+        let value = calculator.evaluate("(-0.5)!").unwrap();
+        assert!((value - std::f64::consts::PI.sqrt()).abs() < 1e-14);
     }
 
     #[test]
-    fn unknown_operator_returns_err() {
-        let res = operate(1.0, 10.0, Rparen);
+    fn factorial_errors() {
+        let mut calculator = Calculator::new();
+        let err = calculator.calculate_infix("1 + (-3)!").unwrap_err();
+        assert_eq!(err.to_string(), "The factorial is not defined for negative integers");
+        assert_eq!(err.span(), &(5..9));
+        assert!(calculator.calculate_infix("(10^6)!").is_err());
+        assert!(calculator.calculate_infix("i!").is_err());
+        assert!(calculator.calculate_infix("!3").is_err());
+    }
 
-        assert!(res.is_err());
+    #[test]
+    fn percentages() {
+        let mut calculator = Calculator::new();
+        assert_eq!(calculator.calculate_infix("20%").unwrap(), "0.2");
+        assert_eq!(calculator.calculate_infix("200 * 15%").unwrap(), "30");
+        assert_eq!(calculator.calculate_infix("50 + 10%").unwrap(), "50.1");
+        assert_eq!(calculator.calculate_infix("(10% - 5) * 10").unwrap(), "-49");
+        assert_eq!(calculator.calculate_infix("10%-5").unwrap(), "-4.9");
+        assert_eq!(calculator.calculate_infix("min(50%, 1)").unwrap(), "0.5");
+
+        // still modulo when followed by an operand
+        assert_eq!(calculator.calculate_infix("7 % 4").unwrap(), "3");
+        assert_eq!(calculator.calculate_infix("7 % (4)").unwrap(), "3");
+        assert_eq!(calculator.calculate_infix("7 % -4").unwrap(), "-1");
     }
 
     #[test]
-    fn divide_by_zero_returns_nan() {
-        let res = operate(1.0, 0.0, Div);
+    fn pocket_calculator_percentages() {
+        let mut calculator = Calculator::new();
+        calculator.set_percent_mode(PercentMode::Pocket);
+        assert_eq!(calculator.calculate_infix("50 + 10%").unwrap(), "55");
+        assert_eq!(calculator.calculate_infix("50 - 10%").unwrap(), "45");
+        assert_eq!(calculator.calculate_infix("2 * 50 + 10%").unwrap(), "110");
+        assert_eq!(calculator.calculate_infix("50 * 10%").unwrap(), "5");
+        assert_eq!(calculator.calculate_infix("10% + 50").unwrap(), "50.1");
+    }
 
-        assert!(res.is_err());
+    #[test]
+    fn implicit_multiplication() {
+        let mut calculator = Calculator::new();
+        calculator.calculate_infix("x = 3").unwrap();
+        assert_eq!(calculator.calculate_infix("2x").unwrap(), "6");
+        assert_eq!(calculator.calculate_infix("2x^2").unwrap(), "18");
+        assert_eq!(calculator.calculate_infix("2(x + 1)").unwrap(), "8");
+        assert_eq!(calculator.calculate_infix("(x - 1)(x + 1)").unwrap(), "8");
+        assert_eq!(calculator.calculate_infix("6/2x").unwrap(), "9");
+        assert_eq!(calculator.calculate_infix("3!x").unwrap(), "18");
+        assert_eq!(calculator.calculate_infix("2 max(x, 1)").unwrap(), "6");
+
+        calculator.calculate_infix("f(x) = 2x + 1").unwrap();
+        assert_eq!(calculator.calculate_infix("f(2)").unwrap(), "5");
+        assert!(calculator.calculate_infix("2 3").is_err());
     }
-}
 
-#[cfg(test)]
-mod calculate_infix_tests {
-    use super::Calculator;
+    #[test]
+    fn variables_and_functions_can_be_listed_and_removed() {
+        let mut calculator = Calculator::new();
+        calculator.calculate_infix("b = 2").unwrap();
+        calculator.calculate_infix("a = 1").unwrap();
+        calculator.calculate_infix("g(x) = x").unwrap();
+        calculator.calculate_infix("f(x, y) = x + y").unwrap();
+        let names: Vec<_> = calculator.variables().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(calculator.functions(), ["f(x, y) = x + y", "g(x) = x"]);
+
+        assert!(calculator.remove("a"));
+        assert!(calculator.remove("g"));
+        assert!(!calculator.remove("a"));
+        assert!(calculator.calculate_infix("a").is_err());
+
+        calculator.clear();
+        assert!(calculator.variables().is_empty());
+        assert!(calculator.functions().is_empty());
+        assert_eq!(calculator.history().len(), 2);
+    }
 
     #[test]
-    fn input_only_operator_doesnt_panic() {
+    fn angle_modes() {
         let mut calculator = Calculator::new();
-        let res = calculator.calculate_infix("+");
-        assert!(res.is_err());
+        calculator.set_angle_mode(AngleMode::Degrees);
+        assert_eq!(calculator.calculate_infix("sin(90)").unwrap(), "1");
+        assert_eq!(calculator.calculate_infix("cos(180) + tan(-360)").unwrap(), "-1");
+        assert_eq!(calculator.calculate_infix("asin(1)").unwrap(), "90");
+        assert_eq!(calculator.calculate_infix("atan2(1, 1)").unwrap(), "45");
+        assert!((calculator.evaluate("sin(30)").unwrap() - 0.5).abs() < 1e-15);
+        let err = calculator.calculate_infix("tan(90)").unwrap_err();
+        assert_eq!(err.to_string(), "tan is not defined for 90");
+
+        calculator.set_angle_mode(AngleMode::Gradians);
+        assert_eq!(calculator.calculate_infix("sin(100)").unwrap(), "1");
+        assert_eq!(calculator.calculate_infix("acos(0)").unwrap(), "100");
+
+        // the other functions don't care about angles
+        assert_eq!(calculator.calculate_infix("sqrt(16)").unwrap(), "4");
     }
 
     #[test]
-    fn parentheses_work() {
+    fn degree_sign_and_conversions() {
         let mut calculator = Calculator::new();
-        let res = calculator.calculate_infix("(1 + 2) * 3");
-        assert_eq!(res.unwrap(), "9");
+        assert_eq!(calculator.calculate_infix("sin(90°)").unwrap(), "1");
+        assert!((calculator.evaluate("180°").unwrap() - std::f64::consts::PI).abs() < 1e-15);
+        assert!((calculator.evaluate("rad(180)").unwrap() - std::f64::consts::PI).abs() < 1e-15);
+        assert_eq!(calculator.calculate_infix("deg(pi)").unwrap(), "180");
+
+        calculator.set_angle_mode(AngleMode::Degrees);
+        assert_eq!(calculator.calculate_infix("sin(90°)").unwrap(), "1");
+        assert_eq!(calculator.calculate_infix("2 * 45°").unwrap(), "90");
+
+        calculator.set_angle_mode(AngleMode::Gradians);
+        assert_eq!(calculator.calculate_infix("cos(90°)").unwrap(), "0");
+        assert_eq!(calculator.calculate_infix("9°").unwrap(), "10");
+        assert!(calculator.calculate_infix("°").is_err());
     }
 
     #[test]
-    fn error_when_extra_before_equals() {
+    fn implicit_multiplication_modes() {
         let mut calculator = Calculator::new();
-        let res = calculator.calculate_infix("a b = 1");
+        calculator.calculate_infix("x = 3").unwrap();
+        calculator.set_implicit_multiplication(ImplicitMultiplication::Tight);
+        assert_eq!(calculator.calculate_infix("6/2x").unwrap(), "1");
+        assert_eq!(calculator.calculate_infix("2x^2").unwrap(), "18");
+        assert_eq!(calculator.calculate_infix("-2x").unwrap(), "-6");
 
-        assert!(res.is_err());
+        calculator.set_implicit_multiplication(ImplicitMultiplication::Off);
+        let err = calculator.calculate_infix("2x").unwrap_err();
+        assert_eq!(err.to_string(), "Too many numbers in a row");
     }
+}
+
+#[cfg(test)]
+mod session_tests {
+    use super::*;
 
     #[test]
-    fn error_equals_without_variable() {
+    fn values_are_restored_exactly() {
         let mut calculator = Calculator::new();
-        let res = calculator.calculate_infix("= 1 + 2");
+        calculator.calculate_infix("a = 0.1 + 0.2").unwrap();
+        calculator.calculate_infix("b = 2 + 3i").unwrap();
+        calculator.calculate_infix("c = -inf").unwrap();
+        calculator.calculate_infix("d = 2^70").unwrap();
+        calculator.calculate_infix("e1 = sqrt(1e40)").unwrap();
+        calculator.calculate_infix("e2 = 2.5").unwrap();
+        calculator.set_number_mode(NumberMode::Exact);
+        calculator.calculate_infix("f = -1/3").unwrap();
+        calculator.set_number_mode(NumberMode::Float);
 
-        assert!(res.is_err());
+        let session = calculator.save();
+        let mut restored = Calculator::new();
+        restored.load(&session).unwrap();
+
+        for ((name, value), (restored_name, restored_value)) in
+            calculator.variables().into_iter().zip(restored.variables())
+        {
+            assert_eq!(name, restored_name);
+            assert_eq!(value.to_string(), restored_value.to_string());
+            assert_eq!(std::mem::discriminant(value), std::mem::discriminant(restored_value));
+        }
+        assert!(matches!(restored.variables()[4].1, Value::Float(x) if *x == 1e20));
+        assert!(matches!(restored.variables()[6].1, Value::Rational(_)));
+        // an integer would make this exact
+        assert!(matches!(restored.evaluate_value("e1^15").unwrap(), Value::Float(_)));
+        assert_eq!(restored.number_mode(), NumberMode::Float);
+        assert_eq!(restored.save(), session);
     }
 
     #[test]
-    fn correct_variables_stored_and_returned() {
+    fn settings_are_restored() {
         let mut calculator = Calculator::new();
-        let res = calculator.calculate_infix("a = 1 + 2");
-        assert_eq!(res.unwrap(), "3");
+        calculator.set_number_mode(NumberMode::Exact);
+        calculator.set_percent_mode(PercentMode::Pocket);
+        calculator.set_rational_display(RationalDisplay::Decimal);
+        calculator.format.update("sci 3 hex").unwrap();
+        // the same body means different things in different modes
+        calculator.calculate_infix("f(x) = 1/2x").unwrap();
+        calculator.set_implicit_multiplication(ImplicitMultiplication::Tight);
+        calculator.calculate_infix("g(x) = 1/2x").unwrap();
+        calculator.set_implicit_multiplication(ImplicitMultiplication::Off);
 
-        let res = calculator.calculate_infix("b = 1 + a");
-        assert_eq!(res.unwrap(), "4");
+        let mut restored = Calculator::new();
+        restored.load(&calculator.save()).unwrap();
+        assert_eq!(restored.number_mode(), NumberMode::Exact);
+        assert_eq!(restored.percent_mode(), PercentMode::Pocket);
+        assert_eq!(restored.implicit_multiplication(), ImplicitMultiplication::Off);
+        assert_eq!(restored.format(), calculator.format());
+        assert_eq!(restored.functions(), ["f(x) = 1/2x", "g(x) = 1/2x"]);
+        assert_eq!(restored.evaluate("f(4)").unwrap(), 2.0);
+        assert_eq!(restored.evaluate("g(4)").unwrap(), 0.125);
+    }
 
-        let res = calculator.calculate_infix("b + a + a");
-        assert_eq!(res.unwrap(), "10");
+    #[test]
+    fn setting_names_are_read_back() {
+        use ImplicitMultiplication::{Normal, Off, Tight};
+        for mode in [Off, Normal, Tight] {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+        }
+        for mode in [AngleMode::Radians, AngleMode::Degrees, AngleMode::Gradians] {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+        }
+        for mode in [NumberMode::Float, NumberMode::Exact] {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+        }
+        for mode in [PercentMode::Plain, PercentMode::Pocket] {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+        }
+        for display in [RationalDisplay::Fraction, RationalDisplay::Decimal] {
+            assert_eq!(display.to_string().parse(), Ok(display));
+        }
+
+        let mut calculator = Calculator::new();
+        calculator.apply_setting("pocket").unwrap();
+        calculator.apply_setting("implicit tight").unwrap();
+        assert_eq!(calculator.percent_mode(), PercentMode::Pocket);
+        assert_eq!(calculator.implicit_multiplication(), ImplicitMultiplication::Tight);
+        let err = calculator.apply_setting("implicit").unwrap_err();
+        assert_eq!(err, "Expected off, normal or tight");
+        assert!(calculator.apply_setting("deg 2").is_err());
     }
 
     #[test]
-    fn error_bad_variable_input() {
+    fn history_is_kept() {
         let mut calculator = Calculator::new();
-        let res = calculator.calculate_infix("* = 1 + 2");
-        assert!(res.is_err());
+        calculator.calculate_infix("1 + 1").unwrap();
+        calculator.load("a = 5\n\n?deg\n").unwrap();
+        assert_eq!(calculator.history(), [Value::from(2)]);
+        assert_eq!(calculator.calculate_infix("a").unwrap(), "5");
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let mut calculator = Calculator::new();
+        calculator.calculate_infix("a = 1").unwrap();
+
+        let (line, err) = calculator.load("b = 2\nc = 1 +\n").unwrap_err();
+        assert_eq!(line, 2);
+        assert_eq!(err.span(), &(6..7));
+        let (line, err) = calculator.load("?deg\n?foo 3").unwrap_err();
+        assert_eq!(line, 2);
+        assert_eq!(err.to_string(), "Unknown setting: ?foo 3");
+
+        // nothing was changed
+        assert_eq!(calculator.variables(), [("a", &Value::from(1))]);
+        assert_eq!(calculator.angle_mode(), AngleMode::Radians);
     }
 }
@@ -0,0 +1,68 @@
+use super::value::Value;
+use num_complex::Complex64;
+use std::f64::consts::{E, PI, TAU};
+
+/// Built-in mathematical constants, as `(name, value)` pairs.
+///
+/// Constants are looked up before the user's own variables and they can't be assigned to.
+/// The imaginary unit `i` works the same way, but isn't listed here as it isn't a real number.
+///
+/// ```
+/// use tiralabra_calculator::{Calculator, CONSTANTS};
+///
+/// let mut calculator = Calculator::new();
+/// for (name, value) in CONSTANTS {
+///     if value.is_finite() {
+///         assert_eq!(calculator.evaluate(name).unwrap(), value);
+///     }
+/// }
+/// ```
+pub const CONSTANTS: [(&str, f64); 5] = [
+    ("pi", PI),
+    ("e", E),
+    ("tau", TAU),
+    ("phi", 1.618_033_988_749_895),
+    ("inf", f64::INFINITY),
+];
+
+/// The name of the imaginary unit
+pub const IMAGINARY_UNIT: &str = "i";
+
+/// Returns the value of the constant called `name`, if there is one.
+///
+/// ```text
+/// get_constant("pi") -> Some(Float(3.141592653589793))
+/// get_constant("i")  -> Some(Complex(0 + 1i))
+/// get_constant("a")  -> None
+/// ```
+pub fn get_constant(name: &str) -> Option<Value> {
+    if name == IMAGINARY_UNIT {
+        return Some(Value::Complex(Complex64::i()));
+    }
+    CONSTANTS
+        .iter()
+        .find(|(constant, _)| *constant == name)
+        .map(|&(_, value)| Value::Float(value))
+}
+
+#[cfg(test)]
+mod constants_tests {
+    use super::*;
+
+    #[test]
+    fn known_constants_are_found() {
+        assert_eq!(get_constant("pi"), Some(Value::Float(PI)));
+        assert_eq!(get_constant("e"), Some(Value::Float(E)));
+        assert_eq!(get_constant("tau"), Some(Value::Float(TAU)));
+        assert_eq!(get_constant("phi"), Some(Value::Float((1.0 + 5.0_f64.sqrt()) / 2.0)));
+        assert_eq!(get_constant("inf"), Some(Value::Float(f64::INFINITY)));
+        assert_eq!(get_constant("i"), Some(Value::Complex(Complex64::new(0.0, 1.0))));
+    }
+
+    #[test]
+    fn other_names_are_not_constants() {
+        assert_eq!(get_constant("a"), None);
+        assert_eq!(get_constant("PI"), None);
+        assert_eq!(get_constant(""), None);
+    }
+}
@@ -1,32 +1,102 @@
-/// Token can represent either a `Number`, a `Float`, a `Variable` or an `Operator`
+use super::error::{CalcError, Span};
+use super::value::Value;
+use num_complex::Complex64;
+use num_integer::Integer;
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive};
+use num_rational::BigRational;
+use std::cmp::Ordering;
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+/// Token can represent either a `Number`, a `Variable`, a reference to an earlier result
+/// (`History`, written as `$3`) or an `Operator`. The jumps only appear in the output of
+/// `shunting_yard`.
 ///
 /// Now, one can create a `Vec<Token>` with numbers and operators mixed without
 /// losing type safety.
 ///
 /// ```
-/// let a = Number(1);
-/// let b = Operator('+');
+/// use tiralabra_calculator::{Operator, Token::{Number, Op}};
+///
+/// let a = Number(1.0.into());
+/// let b = Op(Operator::Plus);
 /// let output = vec![a, b];
 /// ```
 ///
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Token {
-    Number(f64),
+    Number(Value),
     Op(Operator),
-    Variable(String)
+    Variable(String),
+    /// The n:th result of the session, counting from 1
+    History(usize),
+    /// The `i` of an imaginary number like `4i`. It's always the imaginary unit, even in a
+    /// function with a parameter called `i`
+    ImaginaryUnit,
+    /// Pops a condition, and continues from the given index of the postfix expression if
+    /// the condition is false (zero). Used for `if(cond, a, b)`, so that only one of `a` and `b`
+    /// is evaluated
+    JumpIfFalse(usize),
+    /// Continues from the given index of the postfix expression
+    Jump(usize),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Operator {
     Plus,
     Minus,
     Mul,
     Div,
     Pow,
+    /// The remainder of floor division, with the sign of the divisor: `-7 % 3` is 2
+    Mod,
+    /// Division rounded down to an integer, written `//`
+    FloorDiv,
+    BitAnd,
+    BitOr,
+    /// Written as the word `xor`, as `^` is already used for powers
+    BitXor,
+    /// Left shift, `<<`
+    Shl,
+    /// Right shift, `>>`
+    Shr,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    /// `==`, unlike `Equals` which is assignment
+    EqualTo,
+    NotEqual,
+    And,
+    Or,
+    /// Logical negation, a unary operator like `Neg`
+    Not,
+    /// A multiplication left out of the input, as in `2x`, that binds tighter than `Mul`.
+    /// Only inserted with `ImplicitMultiplication::Tight`
+    ImplicitMul,
+    /// Postfix `!`, as in `5!`
+    Factorial,
+    /// Postfix `°`, as in `90°`. Converts degrees to the current `AngleMode`
+    Degree,
+    /// Postfix `%`, as in `20%`. The tokenizer only ever emits `Mod`, `shunting_yard` decides
+    /// from the context which one it is.
+    Percent,
+    /// Unary minus, as in `-x`. The tokenizer only ever emits `Minus`, `shunting_yard` decides
+    /// from the context which one it is.
+    Neg,
+    /// Unary plus, as in `+x`.
+    Pos,
     Lparen,
     Rparen,
     Equals,
-    Func(Function)
+    Comma,
+    /// A function and the number of arguments it was called with. The tokenizer always
+    /// emits a zero here, the real count is filled in by `shunting_yard`.
+    Func(Function, usize),
+    /// A call to a function defined by the user, with the number of arguments like in `Func`
+    UserFunc(String, usize)
 }
 
 impl std::fmt::Display for Operator {
@@ -37,46 +107,659 @@ fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
             Operator::Mul => "*",
             Operator::Div => "/",
             Operator::Pow => "^",
+            Operator::Mod => "%",
+            Operator::FloorDiv => "//",
+            Operator::BitAnd => "&",
+            Operator::BitOr => "|",
+            Operator::BitXor => "xor",
+            Operator::Shl => "<<",
+            Operator::Shr => ">>",
+            Operator::Less => "<",
+            Operator::Greater => ">",
+            Operator::LessEqual => "<=",
+            Operator::GreaterEqual => ">=",
+            Operator::EqualTo => "==",
+            Operator::NotEqual => "!=",
+            Operator::And => "and",
+            Operator::Or => "or",
+            Operator::Not => "not",
+            Operator::ImplicitMul => "*",
+            Operator::Factorial => "!",
+            Operator::Degree => "°",
+            Operator::Percent => "%",
+            Operator::Neg => "-",
+            Operator::Pos => "+",
             Operator::Lparen => "(",
             Operator::Rparen => ")",
             Operator::Equals => "=",
-            Operator::Func(fun) => fun.format()
+            Operator::Comma => ",",
+            Operator::Func(fun, _) => fun.format(),
+            Operator::UserFunc(name, _) => name,
         };
         write!(f, "{c}")
     }
 }
 
+/// A built-in function. The name, the accepted argument counts and the implementation of every
+/// function are in the `FUNCTIONS` table.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Function {
     Sin,
     Cos,
     Tan,
-    Sqrt
+    Asin,
+    Acos,
+    Atan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Asinh,
+    Acosh,
+    Atanh,
+    Sqrt,
+    /// The cube root, which is real for negative numbers too
+    Cbrt,
+    Min,
+    Max,
+    Atan2,
+    /// `log(x)` is the base 10 logarithm, `log(x, base)` uses the given base
+    Log,
+    Log10,
+    Log2,
+    Hypot,
+    Gcd,
+    Lcm,
+    Exp,
+    /// The natural logarithm
+    Ln,
+    /// The real part of a complex number
+    Re,
+    /// The imaginary part of a complex number
+    Im,
+    Abs,
+    /// The angle of a complex number
+    Arg,
+    /// The complex conjugate
+    Conj,
+    Floor,
+    Ceil,
+    /// Rounds halfway cases away from zero
+    Round,
+    /// Rounds towards zero
+    Trunc,
+    /// -1, 0 or 1 for real numbers, `z / abs(z)` for complex ones
+    Sign,
+    Gamma,
+    /// The error function
+    Erf,
+    /// Converts radians to degrees
+    Deg,
+    /// Converts degrees to radians
+    Rad,
+    /// `if(condition, a, b)`. `shunting_yard` turns calls to `if` into jumps, so that only the
+    /// chosen argument is evaluated
+    If,
+    /// Turns an exact number into a floating point number, e.g. `float(1/3)`
+    Float,
+}
+
+/// How a function is calculated
+#[derive(Clone, Copy)]
+enum Implementation {
+    /// A function of one real number, calculated with floats. If the argument is complex, or the
+    /// function isn't defined for the real argument (e.g. `sqrt(-1)`), the complex version is
+    /// used instead, if there is one
+    Real(fn(f64) -> f64, Option<fn(Complex64) -> Complex64>),
+    /// A function of two real numbers, calculated with floats
+    Real2(fn(f64, f64) -> f64),
+    /// Rounds a real number to an integer, exactly if the number is exact
+    Rounding(fn(&BigRational) -> BigRational, fn(f64) -> f64),
+    /// Gets real arguments as they are, e.g. so that exact numbers can stay exact
+    Exact(fn(&[Value]) -> Result<Value, CalcError>),
+    /// Like `Exact`, but the function also handles complex arguments itself
+    Any(fn(&[Value]) -> Result<Value, CalcError>),
 }
 
+/// Whether a function has something to do with angles, so that it depends on the `AngleMode`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Angle {
+    Unrelated,
+    /// The argument is an angle, e.g. `sin`
+    Argument,
+    /// The result is an angle, e.g. `asin`
+    Result,
+}
+
+/// Everything about a function
+struct FunctionInfo {
+    function: Function,
+    name: &'static str,
+    /// The smallest and the largest accepted argument count, see `Function::arity`
+    arity: (usize, Option<usize>),
+    implementation: Implementation,
+    angle: Angle,
+}
+
+/// Shorthand for the table
+const fn info(
+    function: Function,
+    name: &'static str,
+    arity: (usize, Option<usize>),
+    implementation: Implementation,
+) -> FunctionInfo {
+    FunctionInfo { function, name, arity, implementation, angle: Angle::Unrelated }
+}
+
+impl FunctionInfo {
+    const fn angle(self, angle: Angle) -> FunctionInfo {
+        FunctionInfo { angle, ..self }
+    }
+}
+
+const ONE: (usize, Option<usize>) = (1, Some(1));
+const TWO: (usize, Option<usize>) = (2, Some(2));
+const ANY: (usize, Option<usize>) = (1, None);
+
+/// All the built-in functions, in the same order as in `Function`, so that `Function::info` can
+/// find them by their index
+static FUNCTIONS: &[FunctionInfo] = {
+    use self::Function::*;
+    use self::Implementation::*;
+    &[
+        info(Sin, "sin", ONE, Real(f64::sin, Some(Complex64::sin))).angle(Angle::Argument),
+        info(Cos, "cos", ONE, Real(f64::cos, Some(Complex64::cos))).angle(Angle::Argument),
+        info(Tan, "tan", ONE, Real(f64::tan, Some(Complex64::tan))).angle(Angle::Argument),
+        info(Asin, "asin", ONE, Real(f64::asin, Some(Complex64::asin))).angle(Angle::Result),
+        info(Acos, "acos", ONE, Real(f64::acos, Some(Complex64::acos))).angle(Angle::Result),
+        info(Atan, "atan", ONE, Real(f64::atan, Some(Complex64::atan))).angle(Angle::Result),
+        info(Sinh, "sinh", ONE, Real(f64::sinh, Some(Complex64::sinh))),
+        info(Cosh, "cosh", ONE, Real(f64::cosh, Some(Complex64::cosh))),
+        info(Tanh, "tanh", ONE, Real(f64::tanh, Some(Complex64::tanh))),
+        info(Asinh, "asinh", ONE, Real(f64::asinh, Some(Complex64::asinh))),
+        info(Acosh, "acosh", ONE, Real(f64::acosh, Some(Complex64::acosh))),
+        info(Atanh, "atanh", ONE, Real(f64::atanh, Some(Complex64::atanh))),
+        info(Sqrt, "sqrt", ONE, Real(f64::sqrt, Some(Complex64::sqrt))),
+        info(Cbrt, "cbrt", ONE, Real(f64::cbrt, Some(Complex64::cbrt))),
+        info(Min, "min", ANY, Exact(|args| min_max(args, Ordering::Less))),
+        info(Max, "max", ANY, Exact(|args| min_max(args, Ordering::Greater))),
+        info(Atan2, "atan2", TWO, Real2(f64::atan2)).angle(Angle::Result),
+        info(Log, "log", (1, Some(2)), Any(log)),
+        info(Log10, "log10", ONE, Real(f64::log10, Some(Complex64::log10))),
+        info(Log2, "log2", ONE, Real(f64::log2, Some(Complex64::log2))),
+        info(Hypot, "hypot", TWO, Real2(f64::hypot)),
+        info(Gcd, "gcd", ANY, Exact(|args| gcd_lcm(Gcd, args))),
+        info(Lcm, "lcm", ANY, Exact(|args| gcd_lcm(Lcm, args))),
+        info(Exp, "exp", ONE, Real(f64::exp, Some(Complex64::exp))),
+        info(Ln, "ln", ONE, Real(f64::ln, Some(Complex64::ln))),
+        info(Re, "re", ONE, Any(re)),
+        info(Im, "im", ONE, Any(im)),
+        info(Abs, "abs", ONE, Any(abs)),
+        info(Arg, "arg", ONE, Any(arg)),
+        info(Conj, "conj", ONE, Any(conj)),
+        info(Floor, "floor", ONE, Rounding(BigRational::floor, f64::floor)),
+        info(Ceil, "ceil", ONE, Rounding(BigRational::ceil, f64::ceil)),
+        info(Round, "round", ONE, Rounding(BigRational::round, f64::round)),
+        info(Trunc, "trunc", ONE, Rounding(BigRational::trunc, f64::trunc)),
+        info(Sign, "sign", ONE, Any(sign)),
+        info(Gamma, "gamma", ONE, Any(gamma_value)),
+        info(Erf, "erf", ONE, Real(erf, None)),
+        info(Deg, "deg", ONE, Real(f64::to_degrees, Some(|z| z * (180.0 / PI)))),
+        info(Rad, "rad", ONE, Real(f64::to_radians, Some(|z| z * (PI / 180.0)))),
+        info(If, "if", (3, Some(3)), Any(if_else)),
+        info(Float, "float", ONE, Any(float)),
+    ]
+};
+
 impl Function {
-    pub fn evaluate(self, param: f64) -> f64 {
-        match self {
-            Function::Sin => param.sin(),
-            Function::Cos => param.cos(),
-            Function::Tan => param.tan(),
-            Function::Sqrt => param.sqrt()
+    /// All the built-in functions
+    ///
+    /// ```
+    /// use tiralabra_calculator::Function;
+    ///
+    /// assert!(Function::all().any(|fun| fun.format() == "sqrt"));
+    /// ```
+    pub fn all() -> impl Iterator<Item = Function> {
+        FUNCTIONS.iter().map(|info| info.function)
+    }
+
+    fn info(self) -> &'static FunctionInfo {
+        &FUNCTIONS[self as usize]
+    }
+
+    /// Returns the smallest and the largest accepted argument count.
+    /// `None` as the maximum means the function accepts any number of arguments.
+    ///
+    /// ```
+    /// use tiralabra_calculator::Function;
+    ///
+    /// assert_eq!(Function::Sin.arity(), (1, Some(1)));
+    /// assert_eq!(Function::Min.arity(), (1, None));
+    /// ```
+    pub fn arity(self) -> (usize, Option<usize>) {
+        self.info().arity
+    }
+
+    /// Evaluates the function with the given arguments.
+    ///
+    /// Returns an error if the number of arguments doesn't match `arity()`. The error doesn't
+    /// know where the function was called, so its span is empty.
+    /// `log` with one argument is the base 10 logarithm, `log(x, base)` uses the given base.
+    pub fn evaluate(self, args: &[f64]) -> Result<f64, CalcError> {
+        let args: Vec<Value> = args.iter().map(|&x| Value::Float(x)).collect();
+        self.apply(&args).map(|res| res.to_f64())
+    }
+
+    /// Same as `evaluate`, but with any values. `min`, `max`, `gcd`, `lcm`, `abs`, `re`, `im`,
+    /// `conj`, `sign` and the rounding functions keep exact arguments exact, the rest of the
+    /// functions are calculated with floats.
+    ///
+    /// Functions get complex arguments, and e.g. `sqrt`, `ln` and `asin` return complex results
+    /// outside of their real domain. Functions that aren't defined for complex numbers return
+    /// an error.
+    ///
+    /// ```
+    /// use tiralabra_calculator::{Function, Value};
+    ///
+    /// let res = Function::Gcd.apply(&[Value::from(12), Value::from(18)]).unwrap();
+    /// assert_eq!(res, Value::from(6));
+    ///
+    /// let res = Function::Sqrt.apply(&[Value::from(-4)]).unwrap();
+    /// assert_eq!(res.to_string(), "2i");
+    /// ```
+    pub fn apply(self, args: &[Value]) -> Result<Value, CalcError> {
+        self.check_arity(args.len())?;
+        let complex = args.iter().any(Value::is_complex);
+        match self.info().implementation {
+            Implementation::Any(f) => f(args),
+            Implementation::Real(f, complex_version) => {
+                let x = args[0].to_f64();
+                let res = if complex { f64::NAN } else { f(x) };
+                // a NaN argument gives a NaN result, that's not the function's fault
+                if !res.is_nan() || (x.is_nan() && !complex) {
+                    return Ok(Value::Float(res));
+                }
+                match complex_version {
+                    Some(g) => Ok(Value::from(g(args[0].to_complex()))),
+                    None if complex => Err(self.complex_error()),
+                    None => Err(CalcError::Domain {
+                        message: format!("{} is not defined for {}", self.format(), args[0]),
+                        span: Span::default(),
+                    }),
+                }
+            }
+            _ if complex => Err(self.complex_error()),
+            Implementation::Real2(f) => Ok(Value::Float(f(args[0].to_f64(), args[1].to_f64()))),
+            Implementation::Rounding(exact, float) => Ok(match args[0].to_rational() {
+                Some(r) => Value::from(exact(&r)),
+                None => Value::Float(float(args[0].to_f64())),
+            }),
+            Implementation::Exact(f) => f(args),
+        }
+    }
+
+    /// Same as `apply`, but the trigonometric functions and their inverses use angles in `mode`
+    /// instead of radians. In degrees and gradians, multiples of a right angle give exact
+    /// results, e.g. `cos(90)` is exactly 0.
+    ///
+    /// ```
+    /// use tiralabra_calculator::{AngleMode, Function, Value};
+    ///
+    /// let res = Function::Sin.apply_in(&[Value::from(30)], AngleMode::Degrees).unwrap();
+    /// assert!((res.to_f64() - 0.5).abs() < 1e-15);
+    ///
+    /// let res = Function::Acos.apply_in(&[Value::from(0)], AngleMode::Gradians).unwrap();
+    /// assert_eq!(res, Value::from(100));
+    /// ```
+    pub fn apply_in(self, args: &[Value], mode: AngleMode) -> Result<Value, CalcError> {
+        if mode == AngleMode::Radians {
+            return self.apply(args);
+        }
+        match self.info().angle {
+            Angle::Unrelated => self.apply(args),
+            Angle::Argument => {
+                self.check_arity(args.len())?;
+                match self.right_angle_multiple(&args[0], mode)? {
+                    Some(res) => Ok(res),
+                    None => self.apply(&[mode.to_radians(&args[0])]),
+                }
+            }
+            Angle::Result => Ok(mode.radians_to(&self.apply(args)?)),
+        }
+    }
+
+    /// `sin`, `cos` or `tan` of a whole number of right angles, which are exact
+    fn right_angle_multiple(
+        self,
+        angle: &Value,
+        mode: AngleMode,
+    ) -> Result<Option<Value>, CalcError> {
+        let quarters = angle.to_f64() / (mode.full_turn() / 4.0);
+        if angle.is_complex() || !quarters.is_finite() || quarters.fract() != 0.0 {
+            return Ok(None);
+        }
+        let quarter = quarters.rem_euclid(4.0) as usize;
+        let res = match self {
+            Function::Sin => [0, 1, 0, -1][quarter],
+            Function::Cos => [1, 0, -1, 0][quarter],
+            Function::Tan if quarter == 0 || quarter == 2 => 0,
+            Function::Tan => {
+                return Err(CalcError::Domain {
+                    message: format!("tan is not defined for {angle}"),
+                    span: Span::default(),
+                });
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(Value::from(res)))
+    }
+
+    fn complex_error(self) -> CalcError {
+        CalcError::Domain {
+            message: format!("{} is not defined for complex numbers", self.format()),
+            span: Span::default(),
+        }
+    }
+
+    /// Returns an error if the function can't be called with `got` arguments
+    pub(crate) fn check_arity(self, got: usize) -> Result<(), CalcError> {
+        let name = self.format();
+        let message = match self.arity() {
+            (min, None) if got < min => {
+                let plural = if min == 1 { "" } else { "s" };
+                Some(format!("{name} expects at least {min} argument{plural}, got {got}"))
+            }
+            (min, Some(max)) if min == max && got != min => {
+                let plural = if min == 1 { "" } else { "s" };
+                Some(format!("{name} expects {min} argument{plural}, got {got}"))
+            }
+            (min, Some(max)) if got < min || got > max => {
+                Some(format!("{name} expects {min} to {max} arguments, got {got}"))
+            }
+            _ => None,
+        };
+        match message {
+            Some(message) => Err(CalcError::Arity { message, span: Span::default() }),
+            None => Ok(()),
         }
     }
 
     pub fn format(self) -> &'static str {
+        self.info().name
+    }
+}
+
+/// The unit of angles in the trigonometric functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AngleMode {
+    /// This is the default
+    Radians,
+    Degrees,
+    /// A right angle is 100 gradians
+    Gradians,
+}
+
+/// The mode as it's written in the settings: `rad`, `deg` or `grad`
+impl fmt::Display for AngleMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AngleMode::Radians => "rad",
+            AngleMode::Degrees => "deg",
+            AngleMode::Gradians => "grad",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for AngleMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rad" => Ok(AngleMode::Radians),
+            "deg" => Ok(AngleMode::Degrees),
+            "grad" => Ok(AngleMode::Gradians),
+            _ => Err("Expected rad, deg or grad".to_string()),
+        }
+    }
+}
+
+impl AngleMode {
+    /// A full turn in this unit
+    pub(crate) fn full_turn(self) -> f64 {
         match self {
-            Function::Sin => "sin",
-            Function::Cos => "cos",
-            Function::Tan => "tan",
-            Function::Sqrt => "sqrt",
+            AngleMode::Radians => 2.0 * PI,
+            AngleMode::Degrees => 360.0,
+            AngleMode::Gradians => 400.0,
+        }
+    }
+
+    fn to_radians(self, angle: &Value) -> Value {
+        scale(angle, 2.0 * PI / self.full_turn())
+    }
+
+    fn radians_to(self, angle: &Value) -> Value {
+        scale(angle, self.full_turn() / (2.0 * PI))
+    }
+}
+
+/// `value * factor`, calculated with floats
+fn scale(value: &Value, factor: f64) -> Value {
+    match value {
+        Value::Complex(z) => Value::from(z * factor),
+        real => Value::Float(real.to_f64() * factor),
+    }
+}
+
+/// Whether `value` counts as true in a condition, see `Value::to_bool`
+pub(crate) fn truth_value(value: &Value) -> Result<bool, CalcError> {
+    value.to_bool().ok_or_else(|| CalcError::Domain {
+        message: format!("{value} is neither true nor false"),
+        span: Span::default(),
+    })
+}
+
+/// `if(condition, a, b)`, when both `a` and `b` have already been calculated
+fn if_else(args: &[Value]) -> Result<Value, CalcError> {
+    if truth_value(&args[0])? {
+        Ok(args[1].clone())
+    } else {
+        Ok(args[2].clone())
+    }
+}
+
+fn float(args: &[Value]) -> Result<Value, CalcError> {
+    match &args[0] {
+        Value::Complex(z) => Ok(Value::Complex(*z)),
+        real => Ok(real.to_float()),
+    }
+}
+
+/// `log(x)` or `log(x, base)`, which are complex for negative numbers
+fn log(args: &[Value]) -> Result<Value, CalcError> {
+    let negative = args.iter().any(|arg| arg.to_f64() < 0.0);
+    if negative || args.iter().any(Value::is_complex) {
+        let z: Vec<Complex64> = args.iter().map(Value::to_complex).collect();
+        let res = match z[..] {
+            [x, base] => x.ln() / base.ln(),
+            _ => z[0].log10(),
+        };
+        return Ok(Value::from(res));
+    }
+    let res = match args {
+        [x, base] => x.to_f64().log(base.to_f64()),
+        _ => args[0].to_f64().log10(),
+    };
+    Ok(Value::Float(res))
+}
+
+/// `gcd` or `lcm` of integers
+fn gcd_lcm(fun: Function, args: &[Value]) -> Result<Value, CalcError> {
+    let mut ints = Vec::new();
+    for arg in args {
+        ints.push(arg.to_integer().ok_or_else(|| CalcError::Domain {
+            message: format!("{} expects integers, got {arg}", fun.format()),
+            span: Span::default(),
+        })?);
+    }
+    let first = ints[0].abs();
+    let res = if fun == Function::Gcd {
+        ints.iter().skip(1).fold(first, |acc, n| acc.gcd(n))
+    } else {
+        ints.iter().skip(1).fold(first, |acc, n| acc.lcm(n))
+    };
+    Ok(Value::Int(res))
+}
+
+// The functions for complex numbers keep real arguments exact, e.g. `re(1/3)` is `1/3`
+
+fn re(args: &[Value]) -> Result<Value, CalcError> {
+    match &args[0] {
+        Value::Complex(z) => Ok(Value::Float(z.re)),
+        real => Ok(real.clone()),
+    }
+}
+
+fn im(args: &[Value]) -> Result<Value, CalcError> {
+    match &args[0] {
+        Value::Complex(z) => Ok(Value::Float(z.im)),
+        _ => Ok(Value::from(0)),
+    }
+}
+
+fn abs(args: &[Value]) -> Result<Value, CalcError> {
+    match &args[0] {
+        Value::Complex(z) => Ok(Value::Float(z.norm())),
+        real if real.to_f64() < 0.0 => Ok(-real.clone()),
+        real => Ok(real.clone()),
+    }
+}
+
+fn arg(args: &[Value]) -> Result<Value, CalcError> {
+    Ok(Value::Float(args[0].to_complex().arg()))
+}
+
+fn conj(args: &[Value]) -> Result<Value, CalcError> {
+    match &args[0] {
+        Value::Complex(z) => Ok(Value::Complex(z.conj())),
+        real => Ok(real.clone()),
+    }
+}
+
+fn sign(args: &[Value]) -> Result<Value, CalcError> {
+    match &args[0] {
+        Value::Complex(z) => Ok(Value::from(z / z.norm())),
+        Value::Float(x) if *x == 0.0 || x.is_nan() => Ok(Value::Float(*x)),
+        Value::Float(x) => Ok(Value::Float(x.signum())),
+        exact => {
+            let sign = exact.partial_cmp(&Value::from(0)).map_or(0, |ordering| ordering as i64);
+            Ok(Value::from(sign))
+        }
+    }
+}
+
+/// `gamma(x)`, which is an error at the poles (0 and the negative integers) and when the
+/// result is too large for a float, like the factorial
+fn gamma_value(args: &[Value]) -> Result<Value, CalcError> {
+    if args[0].is_complex() {
+        return Err(Function::Gamma.complex_error());
+    }
+    let x = args[0].to_f64();
+    let res = gamma(x);
+    let message = if res.is_nan() && !x.is_nan() {
+        format!("gamma is not defined for {}", args[0])
+    } else if res.is_infinite() {
+        "The result of gamma is too large".to_string()
+    } else {
+        return Ok(Value::Float(res));
+    };
+    Err(CalcError::Domain { message, span: Span::default() })
+}
+
+/// The gamma function. For positive integers it's the exact `(x - 1)!` rounded to a float,
+/// otherwise it uses the Lanczos approximation (with g = 7), which is accurate to about
+/// 15 significant digits. It isn't defined for 0 and negative integers, where it gives NaN.
+pub(crate) fn gamma(x: f64) -> f64 {
+    // 170! is the largest factorial a float can hold
+    const MAX_EXACT: f64 = 171.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x <= 0.0 && x.fract() == 0.0 {
+        f64::NAN
+    } else if x.fract() == 0.0 && x <= MAX_EXACT {
+        let factorial: BigInt = (1..x as u64).map(BigInt::from).product();
+        factorial.to_f64().unwrap_or(f64::INFINITY)
+    } else if x < 0.5 {
+        // the reflection formula
+        PI / ((PI * x).sin() * gamma(1.0 - x))
+    } else {
+        let x = x - 1.0;
+        let t = x + 7.5;
+        let sum = COEFFICIENTS[1..]
+            .iter()
+            .enumerate()
+            .fold(COEFFICIENTS[0], |acc, (i, c)| acc + c / (x + i as f64 + 1.0));
+        (2.0 * PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * sum
+    }
+}
+
+/// The error function. Small arguments use the Taylor series, larger ones a continued fraction
+/// for `1 - erf(x)`, which converges fast when the series doesn't.
+fn erf(x: f64) -> f64 {
+    if x.abs() < 2.5 {
+        // 2/sqrt(pi) * sum of (-1)^n x^(2n + 1) / (n! (2n + 1))
+        let mut term = x;
+        let mut sum = x;
+        for n in 1..100 {
+            term *= -x * x / n as f64;
+            let next = term / (2 * n + 1) as f64;
+            sum += next;
+            if next.abs() < 1e-17 * sum.abs() {
+                break;
+            }
+        }
+        2.0 / PI.sqrt() * sum
+    } else if x.is_nan() {
+        x
+    } else {
+        let y = x.abs();
+        let mut fraction = y;
+        for n in (1..60).rev() {
+            fraction = y + (n as f64 / 2.0) / fraction;
+        }
+        let complement = (-y * y).exp() / PI.sqrt() / fraction;
+        (1.0 - complement).copysign(x)
+    }
+}
+
+/// The smallest (`Ordering::Less`) or the largest (`Ordering::Greater`) of the arguments
+fn min_max(args: &[Value], wanted: Ordering) -> Result<Value, CalcError> {
+    let mut res = &args[0];
+    for arg in args {
+        match arg.partial_cmp(res) {
+            Some(ordering) if ordering == wanted => res = arg,
+            Some(_) => {}
+            None => return Ok(Value::Float(f64::NAN)),
         }
     }
+    Ok(res.clone())
 }
 
 #[cfg(test)]
 mod function_tests {
     use super::Function::*;
+    use super::Value;
+    use num_bigint::BigInt;
+    use num_complex::Complex64;
+    use num_rational::BigRational;
     #[test]
     fn evaluate_first_test() {
         let val: f64 = 1.4;
@@ -84,7 +767,7 @@ fn evaluate_first_test() {
         let correct = [val.sin(), val.cos(), val.tan(), val.sqrt()];
 
         for (f, res) in funcs.iter().zip(correct) {
-            assert_eq!(f.evaluate(val), res);
+            assert_eq!(f.evaluate(&[val]).unwrap(), res);
         }
     }
 
@@ -95,7 +778,7 @@ fn evaluate_second_test() {
         let correct = [val.sin(), val.cos(), val.tan(), val.sqrt()];
 
         for (f, res) in funcs.iter().zip(correct) {
-            assert_eq!(f.evaluate(val), res);
+            assert_eq!(f.evaluate(&[val]).unwrap(), res);
         }
     }
 
@@ -106,7 +789,7 @@ fn evaluate_third_test() {
         let correct = [val.sin(), val.cos(), val.tan(), val.sqrt()];
 
         for (f, res) in funcs.iter().zip(correct) {
-            assert_ne!(f.evaluate(val), res);
+            assert_ne!(f.evaluate(&[val]).unwrap(), res);
         }
     }
 
@@ -119,4 +802,133 @@ fn format_returns_right_values() {
             assert_eq!(f.format(), res);
         }
     }
+
+    #[test]
+    fn multi_argument_functions() {
+        assert_eq!(Min.evaluate(&[3.0, 1.0, 2.0]).unwrap(), 1.0);
+        assert_eq!(Max.evaluate(&[3.0, 1.0, 2.0]).unwrap(), 3.0);
+        assert_eq!(Atan2.evaluate(&[1.0, 1.0]).unwrap(), 1.0_f64.atan2(1.0));
+        assert_eq!(Log.evaluate(&[8.0, 2.0]).unwrap(), 3.0);
+        assert_eq!(Log.evaluate(&[1000.0]).unwrap(), 3.0);
+        assert_eq!(Hypot.evaluate(&[3.0, 4.0]).unwrap(), 5.0);
+        assert_eq!(Gcd.evaluate(&[12.0, -18.0, 8.0]).unwrap(), 2.0);
+        assert_eq!(Lcm.evaluate(&[4.0, 6.0]).unwrap(), 12.0);
+    }
+
+    #[test]
+    fn exact_arguments_stay_exact() {
+        let big = Value::from(BigInt::from(2).pow(100));
+        assert_eq!(Max.apply(&[Value::from(1), big.clone()]).unwrap(), big);
+        assert!(matches!(Min.apply(&[Value::from(1), big]).unwrap(), Value::Int(_)));
+        assert!(matches!(Sqrt.apply(&[Value::from(4)]).unwrap(), Value::Float(_)));
+        assert!(matches!(Float.apply(&[Value::from(2)]).unwrap(), Value::Float(x) if x == 2.0));
+
+        let res = Lcm.apply(&[Value::from(1_000_000_007), Value::from(998_244_353)]).unwrap();
+        assert_eq!(res.to_string(), "998244359987710471");
+    }
+
+    #[test]
+    fn complex_functions() {
+        let z = [Value::Complex(Complex64::new(3.0, -4.0))];
+        assert_eq!(Re.apply(&z).unwrap(), Value::Float(3.0));
+        assert_eq!(Im.apply(&z).unwrap(), Value::Float(-4.0));
+        assert_eq!(Abs.apply(&z).unwrap(), Value::Float(5.0));
+        assert_eq!(Conj.apply(&z).unwrap(), Value::Complex(Complex64::new(3.0, 4.0)));
+        assert_eq!(Arg.apply(&[Value::from(-1)]).unwrap(), Value::Float(std::f64::consts::PI));
+        assert_eq!(Abs.apply(&[Value::from(-7)]).unwrap(), Value::from(7));
+
+        let res = Ln.apply(&[Value::from(-1)]).unwrap();
+        assert_eq!(res, Value::Complex(Complex64::new(0.0, std::f64::consts::PI)));
+
+        let err = Max.apply(&[z[0].clone(), Value::from(1)]).unwrap_err();
+        assert_eq!(err.to_string(), "max is not defined for complex numbers");
+    }
+
+    #[test]
+    fn every_function_is_in_the_table_once() {
+        for (i, info) in super::FUNCTIONS.iter().enumerate() {
+            assert_eq!(info.function as usize, i, "{} is in the wrong place", info.name);
+            assert_eq!(super::Function::all().filter(|fun| fun.format() == info.name).count(), 1);
+        }
+        assert_eq!(If.format(), "if");
+    }
+
+    #[test]
+    fn more_real_functions() {
+        let close = |fun: super::Function, x: f64, expected: f64| {
+            let res = fun.evaluate(&[x]).unwrap();
+            assert!((res - expected).abs() < 1e-14, "{}({x}) = {res}", fun.format());
+        };
+        close(Asin, 1.0, std::f64::consts::FRAC_PI_2);
+        close(Acos, 1.0, 0.0);
+        close(Atan, 1.0, std::f64::consts::FRAC_PI_4);
+        close(Tanh, 0.5, 0.5_f64.tanh());
+        close(Acosh, 1.0, 0.0);
+        close(Cbrt, -27.0, -3.0);
+        close(Log2, 1024.0, 10.0);
+        close(Log10, 0.001, -3.0);
+        close(Gamma, 5.0, 24.0);
+        close(Gamma, 0.5, std::f64::consts::PI.sqrt());
+        close(Gamma, -1.5, 4.0 / 3.0 * std::f64::consts::PI.sqrt());
+        assert_eq!(Gamma.evaluate(&[1.0]).unwrap(), 1.0);
+        assert_eq!(Gamma.evaluate(&[5.0]).unwrap(), 24.0);
+        assert_eq!(Gamma.evaluate(&[21.0]).unwrap(), 2_432_902_008_176_640_000.0);
+        let err = Gamma.evaluate(&[172.0]).unwrap_err();
+        assert_eq!(err.to_string(), "The result of gamma is too large");
+        close(Erf, 0.0, 0.0);
+        close(Erf, 0.5, 0.520_499_877_813_046_5);
+        close(Erf, 2.5, 0.999_593_047_982_555);
+        close(Erf, -3.0, -0.999_977_909_503_001_4);
+    }
+
+    #[test]
+    fn rounding_keeps_exact_values_exact() {
+        let half = [Value::from(BigRational::new(BigInt::from(-5), BigInt::from(2)))];
+        assert_eq!(Floor.apply(&half).unwrap(), Value::from(-3));
+        assert_eq!(Ceil.apply(&half).unwrap(), Value::from(-2));
+        assert_eq!(Round.apply(&half).unwrap(), Value::from(-3));
+        assert_eq!(Trunc.apply(&half).unwrap(), Value::from(-2));
+        assert_eq!(Sign.apply(&half).unwrap(), Value::from(-1));
+        assert!(matches!(Round.apply(&[Value::Float(2.5)]).unwrap(), Value::Float(f) if f == 3.0));
+        assert_eq!(Sign.evaluate(&[0.0]).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn functions_outside_their_real_domain() {
+        let res = Asin.apply(&[Value::from(2)]).unwrap();
+        assert!(res.is_complex());
+        let res = Sign.apply(&[Value::Complex(Complex64::new(3.0, 4.0))]).unwrap();
+        assert_eq!(res, Value::Complex(Complex64::new(0.6, 0.8)));
+
+        let err = Gamma.evaluate(&[-2.0]).unwrap_err();
+        assert_eq!(err.to_string(), "gamma is not defined for -2");
+        let err = Gamma.evaluate(&[0.0]).unwrap_err();
+        assert_eq!(err.to_string(), "gamma is not defined for 0");
+        assert!(Gamma.evaluate(&[f64::INFINITY]).is_err());
+        let err = Gamma.apply(&[Value::Complex(Complex64::new(1.0, 1.0))]).unwrap_err();
+        assert_eq!(err.to_string(), "gamma is not defined for complex numbers");
+        let err = Floor.apply(&[Value::Complex(Complex64::new(1.0, 1.0))]).unwrap_err();
+        assert_eq!(err.to_string(), "floor is not defined for complex numbers");
+    }
+
+    #[test]
+    fn integer_functions_reject_fractions() {
+        let err = Gcd.evaluate(&[2.5, 5.0]).unwrap_err();
+        assert_eq!(err.to_string(), "gcd expects integers, got 2.5");
+    }
+
+    #[test]
+    fn wrong_argument_count_errors() {
+        let err = Min.evaluate(&[]).unwrap_err();
+        assert_eq!(err.to_string(), "min expects at least 1 argument, got 0");
+
+        let err = Atan2.evaluate(&[1.0]).unwrap_err();
+        assert_eq!(err.to_string(), "atan2 expects 2 arguments, got 1");
+
+        let err = Sin.evaluate(&[1.0, 2.0]).unwrap_err();
+        assert_eq!(err.to_string(), "sin expects 1 argument, got 2");
+
+        let err = Log.evaluate(&[1.0, 2.0, 3.0]).unwrap_err();
+        assert_eq!(err.to_string(), "log expects 1 to 2 arguments, got 3");
+    }
 }
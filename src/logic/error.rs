@@ -0,0 +1,127 @@
+use std::fmt;
+use std::ops::Range;
+
+/// A byte range in the input string, e.g. `4..7` for `foo` in `1 + foo`
+pub type Span = Range<usize>;
+
+/// Everything that can go wrong when calculating an expression.
+///
+/// Every error carries the `Span` of the part of the input that caused it, so that the
+/// user interface can point at it. Functions that don't know where in the input they were
+/// called from (e.g. `Function::evaluate`) return an empty span, and the caller replaces it
+/// with `with_span`.
+///
+/// ```
+/// use tiralabra_calculator::{CalcError, Calculator};
+///
+/// let err = Calculator::new().evaluate("1 / 0").unwrap_err();
+/// assert_eq!(err, CalcError::DivisionByZero { span: 4..5 });
+///
+/// let err = CalcError::DivisionByZero { span: 4..5 };
+/// assert_eq!(err.to_string(), "Trying to divide by zero!");
+/// assert_eq!(err.span(), &(4..5));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CalcError {
+    /// The input contains something that can't be turned into a token, e.g. `¦`
+    Lexical { message: String, span: Span },
+    /// The tokens are in an order that doesn't make sense, e.g. `1 + * 2`
+    Syntax { message: String, span: Span },
+    UndefinedVariable { name: String, span: Span },
+    UnknownFunction { name: String, span: Span },
+    /// Trying to assign to a constant, or to redefine a built-in function
+    ReadOnly { message: String, span: Span },
+    /// Referring to a result that isn't in the history (`ans` or `$n`)
+    MissingResult { message: String, span: Span },
+    /// The operation isn't defined for its arguments, e.g. infinite values
+    Domain { message: String, span: Span },
+    DivisionByZero { span: Span },
+    /// A function was called with the wrong number of arguments
+    Arity { message: String, span: Span },
+    /// User-defined functions call each other too deep, e.g. `f(x) = f(x)`
+    Recursion { name: String, span: Span },
+}
+
+impl CalcError {
+    /// The part of the input that caused the error
+    pub fn span(&self) -> &Span {
+        match self {
+            CalcError::Lexical { span, .. }
+            | CalcError::Syntax { span, .. }
+            | CalcError::UndefinedVariable { span, .. }
+            | CalcError::UnknownFunction { span, .. }
+            | CalcError::ReadOnly { span, .. }
+            | CalcError::MissingResult { span, .. }
+            | CalcError::Domain { span, .. }
+            | CalcError::DivisionByZero { span }
+            | CalcError::Arity { span, .. }
+            | CalcError::Recursion { span, .. } => span,
+        }
+    }
+
+    /// Returns the same error, but pointing at `span` instead
+    pub fn with_span(mut self, new_span: Span) -> CalcError {
+        match &mut self {
+            CalcError::Lexical { span, .. }
+            | CalcError::Syntax { span, .. }
+            | CalcError::UndefinedVariable { span, .. }
+            | CalcError::UnknownFunction { span, .. }
+            | CalcError::ReadOnly { span, .. }
+            | CalcError::MissingResult { span, .. }
+            | CalcError::Domain { span, .. }
+            | CalcError::DivisionByZero { span }
+            | CalcError::Arity { span, .. }
+            | CalcError::Recursion { span, .. } => *span = new_span,
+        }
+        self
+    }
+
+    /// Shorthand for the most common kind of error
+    pub(crate) fn syntax(message: impl Into<String>, span: Span) -> CalcError {
+        CalcError::Syntax { message: message.into(), span }
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CalcError::Lexical { message, .. }
+            | CalcError::Syntax { message, .. }
+            | CalcError::ReadOnly { message, .. }
+            | CalcError::MissingResult { message, .. }
+            | CalcError::Domain { message, .. }
+            | CalcError::Arity { message, .. } => write!(f, "{message}"),
+            CalcError::UndefinedVariable { name, .. } => write!(f, "Undefined variable: {name}"),
+            CalcError::UnknownFunction { name, .. } => write!(f, "Unknown function: {name}"),
+            CalcError::DivisionByZero { .. } => write!(f, "Trying to divide by zero!"),
+            CalcError::Recursion { name, .. } => {
+                write!(f, "Maximum recursion depth exceeded when calling {name}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CalcError {}
+
+#[cfg(test)]
+mod calc_error_tests {
+    use super::*;
+
+    #[test]
+    fn with_span_replaces_span() {
+        let err = CalcError::UndefinedVariable { name: "a".to_string(), span: 0..0 };
+        let err = err.with_span(3..4);
+
+        assert_eq!(err.span(), &(3..4));
+        assert_eq!(err, CalcError::UndefinedVariable { name: "a".to_string(), span: 3..4 });
+    }
+
+    #[test]
+    fn display_shows_message() {
+        let err = CalcError::syntax("Too many numbers in a row", 2..3);
+        assert_eq!(err.to_string(), "Too many numbers in a row");
+
+        let err = CalcError::UnknownFunction { name: "f".to_string(), span: 0..1 };
+        assert_eq!(err.to_string(), "Unknown function: f");
+    }
+}
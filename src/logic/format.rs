@@ -0,0 +1,507 @@
+use super::value::Value;
+use num_traits::Signed;
+use std::fmt;
+use std::str::FromStr;
+
+/// How results are shown. The default shows every number the same way as `Value`'s `Display`.
+///
+/// ```
+/// use tiralabra_calculator::{Format, Notation, Radix, Value};
+///
+/// let mut format = Format::default();
+/// format.notation = Notation::Scientific(3);
+/// assert_eq!(format.apply(&Value::from(123456)), "1.23e5");
+///
+/// format.update("hex group").unwrap();
+/// assert_eq!(format.apply(&Value::from(0xDEADBEEF_i64)), "0xDEAD_BEEF");
+/// assert_eq!(format.radix, Radix::Hexadecimal);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Format {
+    pub notation: Notation,
+    /// Integers (and floats without a fractional part) are shown in this base,
+    /// other numbers always in decimal
+    pub radix: Radix,
+    /// Separate groups of digits with `_`, e.g. `1_000_000` or `0xFFFF_FFFF`. Numbers written
+    /// this way can also be used as input.
+    pub grouping: bool,
+    pub rational: RationalDisplay,
+}
+
+/// How the digits of a (decimal) number are laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    /// As many digits as needed. This is the default
+    Auto,
+    /// A fixed number of decimal places, e.g. `3.14` with 2
+    Fixed(usize),
+    /// Rounded to a number of significant digits, e.g. `3.142` with 4
+    Significant(usize),
+    /// A mantissa with the given number of significant digits, and an exponent: `1.235e4`
+    Scientific(usize),
+    /// Like `Scientific`, but the exponent is always a multiple of three: `12.35e3`
+    Engineering(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    Decimal,
+    Hexadecimal,
+    Binary,
+    Octal,
+}
+
+/// How exact fractions are shown in results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RationalDisplay {
+    /// As a fraction, e.g. `1/3`. This is the default
+    Fraction,
+    /// As a decimal number, using the `Notation` of the `Format`
+    Decimal,
+}
+
+/// The setting as it's written in the REPL: `fraction` or `decimal`
+impl fmt::Display for RationalDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RationalDisplay::Fraction => "fraction",
+            RationalDisplay::Decimal => "decimal",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for RationalDisplay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fraction" => Ok(RationalDisplay::Fraction),
+            "decimal" => Ok(RationalDisplay::Decimal),
+            _ => Err("Expected fraction or decimal".to_string()),
+        }
+    }
+}
+
+/// So that e.g. `?format fix 100000` doesn't print a hundred thousand digits
+const MAX_DIGITS: usize = 1000;
+
+impl Default for Format {
+    fn default() -> Format {
+        Format {
+            notation: Notation::Auto,
+            radix: Radix::Decimal,
+            grouping: false,
+            rational: RationalDisplay::Fraction,
+        }
+    }
+}
+
+impl Format {
+    /// Formats `value` according to the settings
+    pub fn apply(&self, value: &Value) -> String {
+        match value {
+            Value::Rational(r) if self.rational == RationalDisplay::Fraction => {
+                let numer = self.group(&r.numer().to_string(), 3);
+                format!("{numer}/{}", self.group(&r.denom().to_string(), 3))
+            }
+            Value::Rational(_) => self.real(&value.to_float()),
+            // `0x3 + 0x4i` couldn't be typed back in, so complex numbers are always decimal
+            Value::Complex(c) => {
+                let decimal = Format { radix: Radix::Decimal, ..*self };
+                complex_to_string(c.re, c.im, |x| decimal.real(&Value::Float(x)))
+            }
+            _ => self.real(value),
+        }
+    }
+
+    /// Changes the settings mentioned in `settings`, a list of words separated by whitespace:
+    ///
+    /// ```text
+    /// auto, fix N, sig N, sci N, eng N   the notation
+    /// dec, hex, bin, oct                 the radix
+    /// group, nogroup                     grouping
+    /// ```
+    ///
+    /// Nothing is changed if any of the settings is invalid.
+    pub fn update(&mut self, settings: &str) -> Result<(), String> {
+        let mut new = *self;
+        let mut words = settings.split_whitespace();
+        while let Some(word) = words.next() {
+            let mut digits = || -> Result<usize, String> {
+                let digits = words
+                    .next()
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .ok_or_else(|| format!("Expected a number of digits after '{word}'"))?;
+                if digits > MAX_DIGITS {
+                    return Err(format!("At most {MAX_DIGITS} digits can be shown"));
+                }
+                if digits == 0 && word != "fix" {
+                    return Err("At least one significant digit has to be shown".to_string());
+                }
+                Ok(digits)
+            };
+            match word {
+                "auto" => new.notation = Notation::Auto,
+                "fix" => new.notation = Notation::Fixed(digits()?),
+                "sig" => new.notation = Notation::Significant(digits()?),
+                "sci" => new.notation = Notation::Scientific(digits()?),
+                "eng" => new.notation = Notation::Engineering(digits()?),
+                "dec" => new.radix = Radix::Decimal,
+                "hex" => new.radix = Radix::Hexadecimal,
+                "bin" => new.radix = Radix::Binary,
+                "oct" => new.radix = Radix::Octal,
+                "group" => new.grouping = true,
+                "nogroup" => new.grouping = false,
+                _ => return Err(format!("Unknown format setting: {word}")),
+            }
+        }
+        *self = new;
+        Ok(())
+    }
+
+    /// Formats a value that isn't complex, or a fraction shown as a fraction
+    fn real(&self, value: &Value) -> String {
+        if let (Some(n), Some(radix)) = (value.to_integer(), self.radix.base()) {
+            let digits = n.abs().to_str_radix(radix).to_uppercase();
+            let group_size = if radix == 8 { 3 } else { 4 };
+            let sign = if n.is_negative() { "-" } else { "" };
+            return format!("{sign}{}{}", self.radix.prefix(), self.group(&digits, group_size));
+        }
+
+        let decimal = match (self.notation, Digits::from_value(value)) {
+            (Notation::Auto, _) | (_, None) => return self.group(&value.to_string(), 3),
+            (_, Some(decimal)) => decimal,
+        };
+        match self.notation {
+            Notation::Auto => unreachable!("handled above"),
+            Notation::Fixed(decimals) => {
+                let significant = decimal.exponent + 1 + decimals as i64;
+                let digits = decimal.round(significant);
+                self.group(&digits.plain(decimals), 3)
+            }
+            Notation::Significant(significant) => {
+                let digits = decimal.round(significant as i64);
+                let decimals = (significant as i64 - 1 - digits.exponent).max(0);
+                self.group(&digits.plain(decimals as usize), 3)
+            }
+            Notation::Scientific(significant) => {
+                decimal.round(significant as i64).scientific(significant, 1)
+            }
+            Notation::Engineering(significant) => {
+                decimal.round(significant as i64).scientific(significant, 3)
+            }
+        }
+    }
+
+    /// Adds separators to the integer part of `number` (e.g. `-1234.5678`), if grouping is on
+    fn group(&self, number: &str, size: usize) -> String {
+        if !self.grouping {
+            return number.to_string();
+        }
+        let sign_len = number.len() - number.trim_start_matches('-').len();
+        let (sign, rest) = number.split_at(sign_len);
+        let int_len = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+        let (int, fraction) = rest.split_at(int_len);
+
+        let mut grouped = String::new();
+        for (i, c) in int.chars().enumerate() {
+            if i > 0 && (int.len() - i) % size == 0 {
+                grouped.push('_');
+            }
+            grouped.push(c);
+        }
+        format!("{sign}{grouped}{fraction}")
+    }
+}
+
+impl Radix {
+    fn base(self) -> Option<u32> {
+        match self {
+            Radix::Decimal => None,
+            Radix::Hexadecimal => Some(16),
+            Radix::Binary => Some(2),
+            Radix::Octal => Some(8),
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Radix::Decimal => "",
+            Radix::Hexadecimal => "0x",
+            Radix::Binary => "0b",
+            Radix::Octal => "0o",
+        }
+    }
+}
+
+/// The settings in the same form `Format::update` reads them, e.g. `sci 6 dec nogroup`
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.notation {
+            Notation::Auto => write!(f, "auto")?,
+            Notation::Fixed(n) => write!(f, "fix {n}")?,
+            Notation::Significant(n) => write!(f, "sig {n}")?,
+            Notation::Scientific(n) => write!(f, "sci {n}")?,
+            Notation::Engineering(n) => write!(f, "eng {n}")?,
+        }
+        let radix = match self.radix {
+            Radix::Decimal => "dec",
+            Radix::Hexadecimal => "hex",
+            Radix::Binary => "bin",
+            Radix::Octal => "oct",
+        };
+        let grouping = if self.grouping { "group" } else { "nogroup" };
+        write!(f, " {radix} {grouping}")
+    }
+}
+
+/// Shows a complex number as `a + bi`, with `real` formatting the parts. A zero real part
+/// is left out, and so is an imaginary coefficient of one (`i` instead of `1i`).
+pub(crate) fn complex_to_string(re: f64, im: f64, real: impl Fn(f64) -> String) -> String {
+    let coefficient = if im.abs() == 1.0 { String::new() } else { real(im.abs()) };
+    let sign = if im.is_sign_negative() { "-" } else { "+" };
+    match (re == 0.0, sign) {
+        (true, "+") => format!("{coefficient}i"),
+        (true, _) => format!("-{coefficient}i"),
+        (false, _) => format!("{} {sign} {coefficient}i", real(re)),
+    }
+}
+
+/// A finite real number as decimal digits, for rounding them without losing precision:
+/// the number is `0.d₁d₂d₃... * 10^(exponent + 1)`, i.e. `exponent` is the power of ten
+/// of the first digit.
+#[derive(Debug, Clone, PartialEq)]
+struct Digits {
+    negative: bool,
+    /// No leading or trailing zeros. Empty for zero
+    digits: Vec<u8>,
+    exponent: i64,
+}
+
+impl Digits {
+    /// `None` for values that can't be written with digits (NaN, infinities, complex numbers)
+    fn from_value(value: &Value) -> Option<Digits> {
+        let (negative, digits, exponent) = match value {
+            Value::Int(n) => {
+                let digits = n.abs().to_string();
+                let exponent = digits.len() as i64 - 1;
+                (n.is_negative(), digits, exponent)
+            }
+            Value::Float(x) if x.is_finite() => {
+                // the shortest digits that read back as the same float, e.g. "1.25e-7"
+                let scientific = format!("{:e}", x.abs());
+                let (mantissa, exponent) = scientific.split_once('e')?;
+                (x.is_sign_negative(), mantissa.replace('.', ""), exponent.parse().ok()?)
+            }
+            Value::Rational(_) => return Digits::from_value(&value.to_float()),
+            _ => return None,
+        };
+        let digits = digits.bytes().map(|d| d - b'0').collect();
+        Some(Digits { negative, digits, exponent }.trimmed())
+    }
+
+    fn zero() -> Digits {
+        Digits { negative: false, digits: Vec::new(), exponent: 0 }
+    }
+
+    /// Removes trailing zeros, and makes a zero look like `zero()`
+    fn trimmed(mut self) -> Digits {
+        while self.digits.last() == Some(&0) {
+            self.digits.pop();
+        }
+        if self.digits.is_empty() {
+            return Digits::zero();
+        }
+        self
+    }
+
+    /// Rounds (half away from zero) to `significant` digits.
+    /// If that is zero or less, the number rounds to either zero or a power of ten.
+    fn round(mut self, significant: i64) -> Digits {
+        if significant < 0 {
+            return Digits::zero();
+        }
+        let significant = significant as usize;
+        if self.digits.len() <= significant {
+            return self;
+        }
+        let round_up = self.digits[significant] >= 5;
+        self.digits.truncate(significant);
+        if round_up {
+            // add one to the last digit, carrying over nines
+            let mut i = significant;
+            loop {
+                if i == 0 {
+                    self.digits.insert(0, 1);
+                    self.exponent += 1;
+                    break;
+                }
+                i -= 1;
+                if self.digits[i] == 9 {
+                    self.digits[i] = 0;
+                } else {
+                    self.digits[i] += 1;
+                    break;
+                }
+            }
+        }
+        self.trimmed()
+    }
+
+    fn sign(&self) -> &'static str {
+        if self.negative {
+            "-"
+        } else {
+            ""
+        }
+    }
+
+    /// The number without an exponent, with exactly `decimals` decimal places
+    fn plain(&self, decimals: usize) -> String {
+        let digit = |i: i64| -> char {
+            let index = self.exponent - i;
+            match usize::try_from(index).ok().and_then(|index| self.digits.get(index)) {
+                Some(d) => char::from(b'0' + d),
+                None => '0',
+            }
+        };
+        // `i` is the power of ten of a digit
+        let int: String = (0..=self.exponent.max(0)).rev().map(digit).collect();
+        let fraction: String = (1..=decimals as i64).map(|i| digit(-i)).collect();
+        match decimals {
+            0 => format!("{}{int}", self.sign()),
+            _ => format!("{}{int}.{fraction}", self.sign()),
+        }
+    }
+
+    /// The number with `significant` digits and an exponent that is a multiple of `step`
+    fn scientific(&self, significant: usize, step: i64) -> String {
+        let exponent = self.exponent.div_euclid(step) * step;
+        let int_len = (self.exponent - exponent + 1) as usize;
+        let mut digits: String = self.digits.iter().map(|&d| char::from(b'0' + d)).collect();
+        while digits.len() < significant.max(int_len) {
+            digits.push('0');
+        }
+        let (int, fraction) = digits.split_at(int_len);
+        match fraction {
+            "" => format!("{}{int}e{exponent}", self.sign()),
+            _ => format!("{}{int}.{fraction}e{exponent}", self.sign()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod format_tests {
+    use super::*;
+    use num_bigint::BigInt;
+    use num_complex::Complex64;
+    use num_rational::BigRational;
+
+    fn format(settings: &str) -> Format {
+        let mut format = Format::default();
+        format.update(settings).unwrap();
+        format
+    }
+
+    #[test]
+    fn auto_is_the_same_as_display() {
+        let values = [Value::from(-12), Value::from(0.1), Value::from(1e22), Value::from(f64::NAN)];
+        for value in values {
+            assert_eq!(Format::default().apply(&value), value.to_string());
+        }
+    }
+
+    #[test]
+    fn fixed_decimals() {
+        let fix = format("fix 2");
+        assert_eq!(fix.apply(&Value::from(std::f64::consts::PI)), "3.14");
+        assert_eq!(fix.apply(&Value::from(-2.005)), "-2.01");
+        assert_eq!(fix.apply(&Value::from(9.999)), "10.00");
+        assert_eq!(fix.apply(&Value::from(12)), "12.00");
+        assert_eq!(fix.apply(&Value::from(0.0001)), "0.00");
+        assert_eq!(format("fix 0").apply(&Value::from(0.5)), "1");
+    }
+
+    #[test]
+    fn significant_digits() {
+        let sig = format("sig 3");
+        assert_eq!(sig.apply(&Value::from(std::f64::consts::PI)), "3.14");
+        assert_eq!(sig.apply(&Value::from(123456)), "123000");
+        assert_eq!(sig.apply(&Value::from(0.000123456)), "0.000123");
+        assert_eq!(sig.apply(&Value::from(1.0)), "1.00");
+    }
+
+    #[test]
+    fn scientific_and_engineering() {
+        let sci = format("sci 4");
+        assert_eq!(sci.apply(&Value::from(6.02214e23)), "6.022e23");
+        assert_eq!(sci.apply(&Value::from(-0.00012)), "-1.200e-4");
+        assert_eq!(sci.apply(&Value::from(0)), "0.000e0");
+        assert_eq!(format("sci 1").apply(&Value::from(96)), "1e2");
+
+        let eng = format("eng 4");
+        assert_eq!(eng.apply(&Value::from(12345)), "12.35e3");
+        assert_eq!(eng.apply(&Value::from(0.00012)), "120.0e-6");
+        assert_eq!(format("eng 1").apply(&Value::from(12345)), "10e3");
+    }
+
+    #[test]
+    fn big_integers_keep_their_digits() {
+        let big = Value::from(BigInt::from(10).pow(400) - 1);
+        assert_eq!(format("sci 3").apply(&big), "1.00e400");
+        assert_eq!(format("sig 3").apply(&big).len(), 401);
+    }
+
+    #[test]
+    fn grouping() {
+        let group = format("group");
+        assert_eq!(group.apply(&Value::from(-1234567)), "-1_234_567");
+        assert_eq!(group.apply(&Value::from(1234.5678)), "1_234.5678");
+        assert_eq!(group.apply(&Value::from(123)), "123");
+        assert_eq!(format("fix 1 group").apply(&Value::from(1e6)), "1_000_000.0");
+    }
+
+    #[test]
+    fn other_radixes() {
+        assert_eq!(format("hex").apply(&Value::from(255)), "0xFF");
+        assert_eq!(format("bin").apply(&Value::from(-10)), "-0b1010");
+        assert_eq!(format("oct group").apply(&Value::from(0o7654321)), "0o7_654_321");
+        assert_eq!(format("bin group").apply(&Value::from(240.0)), "0b1111_0000");
+        // only integers, not fractions or complex numbers
+        assert_eq!(format("hex").apply(&Value::from(0.5)), "0.5");
+        let z = Value::from(Complex64::new(3.0, -4.0));
+        assert_eq!(format("hex").apply(&z), "3 - 4i");
+        let z = Value::from(Complex64::new(4096.0, 1.0));
+        assert_eq!(format("bin group").apply(&z), "4_096 + i");
+    }
+
+    #[test]
+    fn fractions_and_complex_numbers() {
+        let third = Value::from(BigRational::new(1.into(), 3.into()));
+        let mut fix = format("fix 3");
+        assert_eq!(fix.apply(&third), "1/3");
+        fix.rational = RationalDisplay::Decimal;
+        assert_eq!(fix.apply(&third), "0.333");
+
+        let z = Value::from(Complex64::new(1.0 / 3.0, -1.0));
+        assert_eq!(fix.apply(&z), "0.333 - i");
+    }
+
+    #[test]
+    fn update_errors() {
+        let mut format = Format::default();
+        assert_eq!(format.update("sci").unwrap_err(), "Expected a number of digits after 'sci'");
+        let err = format.update("sig 0").unwrap_err();
+        assert_eq!(err, "At least one significant digit has to be shown");
+        assert_eq!(format.update("hex foo").unwrap_err(), "Unknown format setting: foo");
+        assert_eq!(format, Format::default());
+    }
+
+    #[test]
+    fn display_can_be_read_back() {
+        let original = format("eng 5 oct group");
+        assert_eq!(original.to_string(), "eng 5 oct group");
+        assert_eq!(format(&original.to_string()), original);
+    }
+}
@@ -0,0 +1,155 @@
+use super::enums::Token::{self, Variable, Op, Number, History, ImaginaryUnit};
+use super::enums::Operator::{
+    Lparen, Rparen, Mul, ImplicitMul, Func, UserFunc, Factorial, Degree,
+};
+use super::error::Span;
+use std::fmt;
+use std::str::FromStr;
+
+/// Whether multiplication can be written without `*`, as in `2x` or `3(a + b)`, and how
+/// tightly it binds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImplicitMultiplication {
+    /// `2x` is an error
+    Off,
+    /// `2x` is the same as `2 * x`, so `1/2x` is `(1/2) * x`. This is the default
+    Normal,
+    /// `2x` binds tighter than `*` and `/`, so `1/2x` is `1 / (2 * x)`. Powers still bind
+    /// tighter, `2x^2` is `2 * (x^2)`
+    Tight,
+}
+
+/// The mode as it's written in the `?implicit` setting: `off`, `normal` or `tight`
+impl fmt::Display for ImplicitMultiplication {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ImplicitMultiplication::Off => "off",
+            ImplicitMultiplication::Normal => "normal",
+            ImplicitMultiplication::Tight => "tight",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for ImplicitMultiplication {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(ImplicitMultiplication::Off),
+            "normal" => Ok(ImplicitMultiplication::Normal),
+            "tight" => Ok(ImplicitMultiplication::Tight),
+            _ => Err("Expected off, normal or tight".to_string()),
+        }
+    }
+}
+
+/// Inserts the multiplications left out of the input, e.g. `2x` -> `2 * x`. This is done
+/// between tokenizing and `shunting_yard`.
+///
+/// A multiplication is added after a number, a variable, a `)`, a `!` or a `°`, if it's followed
+/// by a variable, a `(` or a function call: `2x`, `2 pi`, `3(a + b)`, `(a)(b)`, `x y`, `2 sin(x)`.
+/// Two numbers in a row (`2 3`) are still an error.
+///
+/// An imaginary number like `4i` is a number followed by `ImaginaryUnit`, so it's multiplied
+/// here too, even when implicit multiplication is off.
+///
+/// ```
+/// use tiralabra_calculator::{implicit_multiplication, tokenize, ImplicitMultiplication};
+/// use tiralabra_calculator::{Operator::Mul, Token::{Number, Op, Variable}};
+///
+/// let tokens = tokenize("2x").unwrap();
+/// let res = implicit_multiplication(tokens, ImplicitMultiplication::Normal);
+///
+/// assert_eq!(res, vec![Number(2.into()), Op(Mul), Variable("x".to_string())]);
+/// ```
+pub fn implicit_multiplication(input: Vec<Token>, mode: ImplicitMultiplication) -> Vec<Token> {
+    let input = input.into_iter().map(|token| (token, Span::default())).collect();
+    let output = implicit_multiplication_spanned(input, mode);
+    output.into_iter().map(|(token, _)| token).collect()
+}
+
+/// Same as `implicit_multiplication`, but for tokens with spans (see `tokenize_spanned`).
+/// The added multiplications get the (usually empty) span between their operands.
+pub fn implicit_multiplication_spanned(
+    input: Vec<(Token, Span)>,
+    mode: ImplicitMultiplication,
+) -> Vec<(Token, Span)> {
+    let op = match mode {
+        ImplicitMultiplication::Off => None,
+        ImplicitMultiplication::Normal => Some(Mul),
+        ImplicitMultiplication::Tight => Some(ImplicitMul),
+    };
+
+    let mut output: Vec<(Token, Span)> = Vec::with_capacity(input.len());
+    for (token, span) in input {
+        if let Some((previous, previous_span)) = output.last() {
+            // only `4i` written together, `4 i` is a normal implicit multiplication
+            let imaginary = token == ImaginaryUnit
+                && matches!(previous, Number(_))
+                && previous_span.end == span.start;
+            let op = op.clone().or_else(|| imaginary.then_some(Mul));
+            if let Some(op) = op.filter(|_| ends_operand(previous) && starts_operand(&token)) {
+                let gap = previous_span.end..span.start;
+                output.push((Op(op), gap));
+            }
+        }
+        output.push((token, span));
+    }
+    output
+}
+
+/// Whether `token` can be the last token of an operand
+fn ends_operand(token: &Token) -> bool {
+    matches!(
+        token,
+        Number(_) | Variable(_) | History(_) | ImaginaryUnit | Op(Rparen | Factorial | Degree)
+    )
+}
+
+/// Whether `token` can be the first token of an implicitly multiplied operand. Numbers can't,
+/// as `2 3` is more likely a typo than `2 * 3`
+fn starts_operand(token: &Token) -> bool {
+    matches!(token, Variable(_) | ImaginaryUnit | Op(Lparen | Func(..) | UserFunc(..)))
+}
+
+#[cfg(test)]
+mod implicit_tests {
+    use super::*;
+    use crate::logic::{tokenize, tokenize_spanned};
+
+    fn with_mode(input: &str, mode: ImplicitMultiplication) -> Vec<Token> {
+        implicit_multiplication(tokenize(input).unwrap(), mode)
+    }
+
+    #[test]
+    fn multiplications_are_inserted() {
+        let inputs = ["2x", "2 pi", "3(a)", "(a)(b)", "x y", "2 sin(x)", "3!x", "$1 x", "(a)b"];
+        for input in inputs {
+            let res = with_mode(input, ImplicitMultiplication::Normal);
+            assert_eq!(res.iter().filter(|&token| *token == Op(Mul)).count(), 1, "{input}");
+        }
+    }
+
+    #[test]
+    fn other_tokens_are_left_alone() {
+        let inputs = ["2 3", "(1) 2", "f(x)", "x - y", "-x", "sin(x)", "x = 2"];
+        for input in inputs {
+            let tokens = tokenize(input).unwrap();
+            let res = implicit_multiplication(tokens.clone(), ImplicitMultiplication::Normal);
+            assert_eq!(res, tokens, "{input}");
+        }
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(with_mode("2x", ImplicitMultiplication::Off), tokenize("2x").unwrap());
+        assert_eq!(with_mode("2i", ImplicitMultiplication::Off)[1], Op(Mul));
+        assert_eq!(with_mode("2 i", ImplicitMultiplication::Off), tokenize("2 i").unwrap());
+        assert_eq!(with_mode("2x", ImplicitMultiplication::Tight)[1], Op(ImplicitMul));
+
+        let tokens = tokenize_spanned("2  x").unwrap();
+        let res = implicit_multiplication_spanned(tokens, ImplicitMultiplication::Normal);
+        assert_eq!(res[1], (Op(Mul), 1..3));
+    }
+}
@@ -1,22 +1,80 @@
-use super::enums::Token::{self, Variable, Op, Number};
-use super::enums::Operator::{self, Lparen, Rparen, Plus, Minus, Mul, Div, Pow, Func};
+use super::enums::Token::{self, Variable, Op, Number, History, ImaginaryUnit, JumpIfFalse, Jump};
+use super::enums::Operator::{
+    self, Lparen, Rparen, Comma, Equals, Plus, Minus, Mul, Div, Pow, Neg, Pos, Func, UserFunc, Mod,
+    FloorDiv, BitAnd, BitOr, BitXor, Shl, Shr, Less, Greater, LessEqual, GreaterEqual, EqualTo,
+    NotEqual, And, Or, Not, Factorial, Percent, ImplicitMul, Degree,
+};
+use super::enums::Function;
+use super::error::{CalcError, Span};
 // use super::enums::Number::{Integer, Float};
 /// Returns the precedence value for given operator, as described in
 /// [here](https://en.wikipedia.org/wiki/Shunting-yard_algorithm#Detailed_example):
 ///
-/// `+`, `-` -> 2,
-/// `*`, `/` -> 3,
-/// `^` -> 4
+/// `or` -> 1,
+/// `and` -> 2,
+/// `not` -> 3,
+/// `<`, `>`, `<=`, `>=`, `==`, `!=` -> 4,
+/// `|` -> 5,
+/// `xor` -> 6,
+/// `&` -> 7,
+/// `<<`, `>>` -> 8,
+/// `+`, `-` -> 9,
+/// `*`, `/`, `//`, `%` -> 10,
+/// implicit multiplication (with `ImplicitMultiplication::Tight`) -> 11,
+/// unary `-`, `+` -> 12,
+/// `^` -> 13
 ///
+/// The postfix operators `!`, `%` and `°` have no precedence, as they are moved to the output
+/// right away: they always apply to the operand just before them, so `-2^3!` is `-(2^(3!))`.
+///
+/// The bitwise operators are ordered like in C, but all of them bind looser than arithmetic,
+/// so `1 << 2 + 3` is `1 << (2 + 3)`. Unary minus binds looser than `^`, so `-2^2` is `-(2^2)`.
+/// The comparisons and the logical operators are ordered like in Python, so
+/// `not x < 0 and y & 1 == 1` is `(not (x < 0)) and ((y & 1) == 1)`.
+///
+/// ```text
+/// precedence(&Plus)   -> Some(9)
+/// precedence(&Lparen) -> None
 /// ```
-/// precedence('+'); // Some(2)
-/// precedence('h'); // None
-/// ```
-fn precedence(op: Operator) -> Option<u8> {
+fn precedence(op: &Operator) -> Option<u8> {
     match op {
-        Plus | Minus => Some(2),
-        Mul | Div => Some(3),
-        Pow => Some(4),
+        Or => Some(1),
+        And => Some(2),
+        Not => Some(3),
+        Less | Greater | LessEqual | GreaterEqual | EqualTo | NotEqual => Some(4),
+        BitOr => Some(5),
+        BitXor => Some(6),
+        BitAnd => Some(7),
+        Shl | Shr => Some(8),
+        Plus | Minus => Some(9),
+        Mul | Div | FloorDiv | Mod => Some(10),
+        ImplicitMul => Some(11),
+        Neg | Pos => Some(12),
+        Pow => Some(13),
+        _ => None,
+    }
+}
+
+/// Whether a sequence of operators with equal precedence groups from the left or from the right.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Associativity {
+    Left,
+    Right,
+}
+
+/// Returns the associativity of the given operator:
+///
+/// binary operators other than `^` -> left (`8 - 3 - 2` is `(8 - 3) - 2`),
+/// `^`, unary `-`, `+`, `not` -> right (`2 ^ 3 ^ 2` is `2 ^ (3 ^ 2)`)
+///
+/// Comparisons are left-associative too, so `3 > 2 > 1` is `(3 > 2) > 1`, which is false.
+fn associativity(op: &Operator) -> Option<Associativity> {
+    match op {
+        Plus | Minus | Mul | Div | FloorDiv | Mod | ImplicitMul => Some(Associativity::Left),
+        BitAnd | BitOr | BitXor | Shl | Shr => Some(Associativity::Left),
+        Less | Greater | LessEqual | GreaterEqual | EqualTo | NotEqual => Some(Associativity::Left),
+        And | Or => Some(Associativity::Left),
+        Pow | Neg | Pos | Not => Some(Associativity::Right),
         _ => None,
     }
 }
@@ -27,118 +85,299 @@ fn precedence(op: Operator) -> Option<u8> {
 /// input: Vec<Token> formed from an expression, with e.g. the `tokenize`-function in
 /// `logic::tokenize`
 ///
-/// Returns `Result` with either a `Vec` of `Token`'s, or an error
+/// Returns `Result` with either a `Vec` of `Token`'s, or an error. As the tokens don't know
+/// where in the input they came from, the spans of the errors are empty; use
+/// `shunting_yard_spanned` to get meaningful ones.
 ///
 /// ```
+/// use tiralabra_calculator::{shunting_yard, tokenize, Operator::{Mul, Plus}, Token::{Number, Op}};
+///
 /// let input = "1 + 2 * 4";
 /// let tokens = tokenize(input).unwrap();
 /// let res = shunting_yard(tokens).unwrap();
-/// // -> [Number(1.0), Number(2.0), Number(4.0), Operator('*'), Operator('+')]
+///
+/// let correct = vec![Number(1.0.into()), Number(2.0.into()), Number(4.0.into()), Op(Mul), Op(Plus)];
+/// assert_eq!(res, correct);
 /// ```
-pub fn shunting_yard(input: Vec<Token>) -> Result<Vec<Token>, String> {
+pub fn shunting_yard(input: Vec<Token>) -> Result<Vec<Token>, CalcError> {
+    let input = input.into_iter().map(|token| (token, Span::default())).collect();
+    let output = shunting_yard_spanned(input)?;
+    Ok(output.into_iter().map(|(token, _)| token).collect())
+}
+
+/// Same as `shunting_yard`, but for tokens with spans (see `tokenize_spanned`).
+///
+/// The spans are kept in the output, so that errors found while evaluating can point at the
+/// input. A function call gets a span from its name to its closing parenthesis.
+///
+/// `if(cond, a, b)` doesn't become a function call. Instead it's turned into jumps, so that only
+/// one of `a` and `b` gets evaluated: `cond JumpIfFalse(x) a Jump(y) b`, where `x` is the index
+/// of the first token of `b` and `y` the index right after `b`.
+///
+/// A `%` is the percent sign instead of modulo, if it can't be followed by an operand (see
+/// `is_percent`).
+pub fn shunting_yard_spanned(input: Vec<(Token, Span)>) -> Result<Vec<(Token, Span)>, CalcError> {
     let mut output = Vec::new();
-    let mut operators = Vec::new();
+    let mut operators: Vec<(Operator, Span)> = Vec::new();
+
+    // one counter for every currently open parenthesis, keeping track of how many arguments
+    // have been given inside it. Only counters belonging to a function call can go above one
+    let mut arg_counts: Vec<usize> = Vec::new();
+
+    // indices of the jumps of the currently open `if` calls, whose targets aren't known yet
+    let mut pending_jumps: Vec<usize> = Vec::new();
 
     // only one operator can occur before a number
     // "1 + 1" is ok, "1 ++ 1" is not
     let mut is_operator_time = false;
 
-    for token in input {
+    // where the input ended, for errors about something missing at the end
+    let mut last_span = Span::default();
+
+    let percents: Vec<bool> = (0..input.len()).map(|i| is_percent(&input, i)).collect();
+
+    for (i, (token, span)) in input.into_iter().enumerate() {
+        last_span = span.clone();
         match token {
-            Op(f @ Func(_)) => operators.push(f),
-            Op(Lparen) => operators.push(Lparen),
+            Op(f @ (Func(..) | UserFunc(..))) => operators.push((f, span)),
+            Op(Lparen) => {
+                operators.push((Lparen, span));
+                arg_counts.push(0);
+            }
+            Op(Comma) => {
+                if !is_operator_time {
+                    return Err(CalcError::syntax("Unexpected comma", span));
+                }
+                is_operator_time = false;
+
+                // everything since the start of the argument belongs to it
+                while operators.last().is_some_and(|(op, _)| *op != Lparen) {
+                    output.push(pop_operator(&mut operators));
+                }
+
+                // the parenthesis has to belong to a function: "min(1, 2)" is ok, "(1, 2)" is not
+                let len = operators.len();
+                if len < 2 || !matches!(operators[len - 2].0, Func(..) | UserFunc(..)) {
+                    return Err(CalcError::syntax("Comma outside of a function call", span));
+                }
+                if let Some(count) = arg_counts.last_mut() {
+                    if matches!(operators[len - 2].0, Func(Function::If, _)) {
+                        match *count {
+                            // after the condition
+                            1 => {
+                                pending_jumps.push(output.len());
+                                output.push((JumpIfFalse(0), span.clone()));
+                            }
+                            // after the value for a true condition, skip the other value
+                            2 => {
+                                let jump = pending_jumps.pop().unwrap();
+                                output.push((Jump(0), span.clone()));
+                                output[jump].0 = JumpIfFalse(output.len());
+                                pending_jumps.push(output.len() - 1);
+                            }
+                            // too many arguments, an error when the call ends
+                            _ => {}
+                        }
+                    }
+                    *count += 1;
+                }
+            }
             Op(Rparen) => {
-                let mut found = false;
-                while let Some(op) = operators.pop() {
-                    if op == Lparen {
-                        found = true;
+                let arg_count = arg_counts.pop().ok_or_else(|| {
+                    CalcError::syntax("Right parenthesis without a pair found", span.clone())
+                })?;
+
+                while let Some((op, _)) = operators.last() {
+                    if *op == Lparen {
                         break;
                     }
-
-                    output.push(Op(op));
+                    output.push(pop_operator(&mut operators));
                 }
+                let (_, lparen_span) = operators.pop().unwrap();
 
-                if !found {
-                    return Err("Right parenthesis without a pair found".to_string());
+                let call = match operators.last() {
+                    Some((Func(fun, _), name_span)) => Some((Func(*fun, arg_count), name_span.start)),
+                    Some((UserFunc(name, _), name_span)) => {
+                        Some((UserFunc(name.to_string(), arg_count), name_span.start))
+                    }
+                    _ => None,
+                };
+
+                if let Some((call, call_start)) = call {
+                    // "min(1, )" is missing an argument, "min()" is fine (no arguments at all)
+                    if !is_operator_time && arg_count > 0 {
+                        return Err(CalcError::syntax("Missing argument before ')'", span));
+                    }
+                    operators.pop();
+                    if let Func(Function::If, _) = call {
+                        Function::If
+                            .check_arity(arg_count)
+                            .map_err(|err| err.with_span(call_start..span.end))?;
+                        let jump = pending_jumps.pop().unwrap();
+                        output[jump].0 = Jump(output.len());
+                    } else {
+                        output.push((Op(call), call_start..span.end));
+                    }
+                } else if arg_count == 0 {
+                    return Err(CalcError::syntax("Empty parentheses", lparen_span.start..span.end));
+                } else if !is_operator_time {
+                    return Err(CalcError::syntax("Unexpected operator before ')'", span));
                 }
+
+                // the parenthesized expression as a whole is an operand
+                is_operator_time = true;
+                mark_operand(&mut arg_counts);
             }
+            // a sign where an operand is expected is unary ("-1", "2 * -x", "-(1 + 2)").
+            // Unary operators have no left operand, so nothing is popped before pushing them
+            Op(Minus) if !is_operator_time => operators.push((Neg, span)),
+            Op(Plus) if !is_operator_time => operators.push((Pos, span)),
+            Op(Not) if !is_operator_time => operators.push((Not, span)),
+            Op(Not) => return Err(CalcError::syntax("Unexpected operator: not", span)),
+            // postfix operators come right after their operand, so they go straight to output
+            Op(Mod) if is_operator_time && percents[i] => output.push((Op(Percent), span)),
+            Op(op @ (Factorial | Degree)) if is_operator_time => output.push((Op(op), span)),
+            Op(Equals) => return Err(CalcError::syntax("Unexpected '='", span)),
             Op(op) => {
                 if !is_operator_time {
-                    return Err(format!("Unexpected operator: {op}"));
+                    return Err(CalcError::syntax(format!("Unexpected operator: {op}"), span));
                 }
                 is_operator_time = false;
-                if let Some(p1) = precedence(op) {
+                if let Some(p1) = precedence(&op) {
                     while !operators.is_empty() {
-                        let last_operator = operators[operators.len() - 1];
-                        if last_operator == Lparen {
+                        let (last_operator, _) = &operators[operators.len() - 1];
+                        if *last_operator == Lparen {
                             break;
                         }
 
+                        // the operator on the stack has to be evaluated first if it binds
+                        // tighter, or equally tight and the new operator is left-associative
                         if let Some(p2) = precedence(last_operator) {
-                            if p2 <= p1 {
+                            let left = associativity(&op) == Some(Associativity::Left);
+                            if p2 < p1 || (p2 == p1 && !left) {
                                 break;
                             }
                         }
-                        output.push(Op(operators.pop().unwrap()));
+                        output.push(pop_operator(&mut operators));
                     }
 
-                    operators.push(op);
+                    operators.push((op, span));
                 }
             }
-            Number(_) | Variable(_) => {
+            Number(_) | Variable(_) | History(_) | ImaginaryUnit => {
                 if is_operator_time {
-                    return Err("Too many numbers in a row".to_string());
+                    return Err(CalcError::syntax("Too many numbers in a row", span));
                 }
                 is_operator_time = true;
-                output.push(token);
+                mark_operand(&mut arg_counts);
+                output.push((token, span));
+            }
+            JumpIfFalse(_) | Jump(_) => {
+                return Err(CalcError::syntax("Unexpected jump in the input", span));
             }
         }
     }
 
-    while let Some(op) = operators.pop() {
+    while let Some((op, span)) = operators.pop() {
         if op == Lparen {
-            return Err("Left parenthesis without a pair found".to_string());
+            return Err(CalcError::syntax("Left parenthesis without a pair found", span));
         }
-        output.push(Op(op));
+        output.push((Op(op), span));
+    }
+
+    // "1 +" is missing an operand at the end (an empty input is fine)
+    if !is_operator_time && !output.is_empty() {
+        return Err(CalcError::syntax("Expression ends with an operator", last_span));
     }
 
     Ok(output)
 }
 
+/// Whether the token at `i` is a `%` used as the percent sign, as in `50 + 10%`.
+///
+/// It's modulo if it's followed by an operand, as in `7 % 3`, and also if it's followed by
+/// a sign that is attached to the operand after it but not to the `%`, as in `7 % -3`.
+/// So `10% - 5` and `10%-5` are both `0.1 - 5`.
+///
+/// ```text
+/// "7 % (1)" -> false
+/// "7 %)"    -> true
+/// "7 % -3"  -> false
+/// "7 % - 3" -> true
+/// ```
+fn is_percent(input: &[(Token, Span)], i: usize) -> bool {
+    if input[i].0 != Op(Mod) {
+        return false;
+    }
+    let starts_operand = |token: &Token| match token {
+        Op(Lparen | Func(..) | UserFunc(..) | Not) => true,
+        Op(_) | JumpIfFalse(_) | Jump(_) => false,
+        Number(_) | Variable(_) | History(_) | ImaginaryUnit => true,
+    };
+    match input.get(i + 1) {
+        Some((Op(Plus | Minus), sign_span)) => match input.get(i + 2) {
+            Some((next, next_span)) => {
+                let attached = sign_span.end == next_span.start;
+                let spaced = input[i].1.end < sign_span.start;
+                !(starts_operand(next) && attached && spaced)
+            }
+            None => true,
+        },
+        Some((next, _)) => !starts_operand(next),
+        None => true,
+    }
+}
+
+/// Moves the topmost operator of the stack into an output token
+fn pop_operator(operators: &mut Vec<(Operator, Span)>) -> (Token, Span) {
+    let (op, span) = operators.pop().unwrap();
+    (Op(op), span)
+}
+
+/// Records that the innermost open parenthesis has at least one argument in it.
+fn mark_operand(arg_counts: &mut [usize]) {
+    if let Some(count) = arg_counts.last_mut() {
+        if *count == 0 {
+            *count = 1;
+        }
+    }
+}
+
 #[cfg(test)]
 mod shunting_yard_tests {
 
     use super::*;
+    use crate::logic::enums::Function;
 
     #[test]
     fn single_digit_works() {
-        let tokens = vec![Number(1.0)];
+        let tokens = vec![Number(1.0.into())];
         let res = shunting_yard(tokens).unwrap();
-        assert_eq!(res[0], Number(1.0));
+        assert_eq!(res[0], Number(1.0.into()));
     }
 
     #[test]
     fn one_plus_one_works() {
-        let tokens = vec![Number(1.0), Op(Plus), Number(1.0)];
+        let tokens = vec![Number(1.0.into()), Op(Plus), Number(1.0.into())];
         let res = shunting_yard(tokens).unwrap();
-        let correct = vec![Number(1.0), Number(1.0), Op(Plus)];
+        let correct = vec![Number(1.0.into()), Number(1.0.into()), Op(Plus)];
         assert_eq!(res, correct);
     }
 
     #[test]
     fn one_plus_two_times_four_works() {
         let tokens = vec![
-            Number(1.0),
+            Number(1.0.into()),
             Op(Plus),
-            Number(2.0),
+            Number(2.0.into()),
             Op(Mul),
-            Number(4.0),
+            Number(4.0.into()),
         ];
         let res = shunting_yard(tokens).unwrap();
         let correct = vec![
-            Number(1.0),
-            Number(2.0),
-            Number(4.0),
+            Number(1.0.into()),
+            Number(2.0.into()),
+            Number(4.0.into()),
             Op(Mul),
             Op(Plus),
         ];
@@ -148,19 +387,19 @@ fn one_plus_two_times_four_works() {
     #[test]
     fn exponents_work() {
         let tokens = vec![
-            Number(2.0),
+            Number(2.0.into()),
             Op(Pow),
-            Number(4.0),
+            Number(4.0.into()),
             Op(Mul),
-            Number(2.0),
+            Number(2.0.into()),
         ];
 
         let res = shunting_yard(tokens).unwrap();
         let correct = vec![
-            Number(2.0),
-            Number(4.0),
+            Number(2.0.into()),
+            Number(4.0.into()),
             Op(Pow),
-            Number(2.0),
+            Number(2.0.into()),
             Op(Mul),
         ];
 
@@ -170,11 +409,11 @@ fn exponents_work() {
     #[test]
     fn mismatched_right_parenthesis_errors() {
         let tokens = vec![
-            Number(1.0),
+            Number(1.0.into()),
             Op(Plus),
-            Number(2.0),
+            Number(2.0.into()),
             Op(Mul),
-            Number(3.0),
+            Number(3.0.into()),
             Op(Rparen),
         ];
         let res = shunting_yard(tokens);
@@ -184,12 +423,12 @@ fn mismatched_right_parenthesis_errors() {
     #[test]
     fn mismatched_left_parenthesis_errors() {
         let tokens = vec![
-            Number(1.0),
+            Number(1.0.into()),
             Op(Plus),
             Op(Lparen),
-            Number(2.0),
+            Number(2.0.into()),
             Op(Mul),
-            Number(3.0),
+            Number(3.0.into()),
         ];
         let res = shunting_yard(tokens);
         assert!(res.is_err());
@@ -197,10 +436,397 @@ fn mismatched_left_parenthesis_errors() {
 
     #[test]
     fn too_many_numbers_in_a_row() {
-        let tokens = vec![Number(1.0), Number(2.0), Op(Mul), Number(100.0)];
+        let tokens = vec![Number(1.0.into()), Number(2.0.into()), Op(Mul), Number(100.0.into())];
 
         let res = shunting_yard(tokens);
 
         assert!(res.is_err());
     }
+
+    #[test]
+    fn function_arguments_are_counted() {
+        // max(1, 2 + 3, 4)
+        let tokens = vec![
+            Op(Func(Function::Max, 0)),
+            Op(Lparen),
+            Number(1.0.into()),
+            Op(Comma),
+            Number(2.0.into()),
+            Op(Plus),
+            Number(3.0.into()),
+            Op(Comma),
+            Number(4.0.into()),
+            Op(Rparen),
+        ];
+        let res = shunting_yard(tokens).unwrap();
+        let correct = vec![
+            Number(1.0.into()),
+            Number(2.0.into()),
+            Number(3.0.into()),
+            Op(Plus),
+            Number(4.0.into()),
+            Op(Func(Function::Max, 3)),
+        ];
+        assert_eq!(res, correct);
+    }
+
+    #[test]
+    fn nested_function_calls() {
+        // min((1), max(2, 3))
+        let tokens = vec![
+            Op(Func(Function::Min, 0)),
+            Op(Lparen),
+            Op(Lparen),
+            Number(1.0.into()),
+            Op(Rparen),
+            Op(Comma),
+            Op(Func(Function::Max, 0)),
+            Op(Lparen),
+            Number(2.0.into()),
+            Op(Comma),
+            Number(3.0.into()),
+            Op(Rparen),
+            Op(Rparen),
+        ];
+        let res = shunting_yard(tokens).unwrap();
+        let correct = vec![
+            Number(1.0.into()),
+            Number(2.0.into()),
+            Number(3.0.into()),
+            Op(Func(Function::Max, 2)),
+            Op(Func(Function::Min, 2)),
+        ];
+        assert_eq!(res, correct);
+    }
+
+    #[test]
+    fn function_without_arguments() {
+        let tokens = vec![Op(Func(Function::Min, 0)), Op(Lparen), Op(Rparen)];
+        let res = shunting_yard(tokens).unwrap();
+        assert_eq!(res, vec![Op(Func(Function::Min, 0))]);
+    }
+
+    #[test]
+    fn comma_outside_function_errors() {
+        let tokens = vec![Op(Lparen), Number(1.0.into()), Op(Comma), Number(2.0.into()), Op(Rparen)];
+        assert!(shunting_yard(tokens).is_err());
+
+        let tokens = vec![Number(1.0.into()), Op(Comma), Number(2.0.into())];
+        assert!(shunting_yard(tokens).is_err());
+    }
+
+    #[test]
+    fn missing_argument_errors() {
+        let tokens = vec![
+            Op(Func(Function::Min, 0)),
+            Op(Lparen),
+            Number(1.0.into()),
+            Op(Comma),
+            Op(Rparen),
+        ];
+        assert!(shunting_yard(tokens).is_err());
+
+        let tokens = vec![Op(Lparen), Op(Rparen)];
+        assert!(shunting_yard(tokens).is_err());
+    }
+
+    #[test]
+    fn bitwise_operators_bind_looser_than_arithmetic() {
+        // 1 | 2 xor 3 & 4 << 5 + 6
+        let tokens = vec![
+            Number(1.into()),
+            Op(BitOr),
+            Number(2.into()),
+            Op(BitXor),
+            Number(3.into()),
+            Op(BitAnd),
+            Number(4.into()),
+            Op(Shl),
+            Number(5.into()),
+            Op(Plus),
+            Number(6.into()),
+        ];
+        let res = shunting_yard(tokens).unwrap();
+        let correct = vec![
+            Number(1.into()),
+            Number(2.into()),
+            Number(3.into()),
+            Number(4.into()),
+            Number(5.into()),
+            Number(6.into()),
+            Op(Plus),
+            Op(Shl),
+            Op(BitAnd),
+            Op(BitXor),
+            Op(BitOr),
+        ];
+        assert_eq!(res, correct);
+    }
+
+    #[test]
+    fn comparisons_bind_looser_than_arithmetic() {
+        // not 1 + 1 < 3 and 0
+        let tokens = vec![
+            Op(Not),
+            Number(1.into()),
+            Op(Plus),
+            Number(1.into()),
+            Op(Less),
+            Number(3.into()),
+            Op(And),
+            Number(0.into()),
+        ];
+        let res = shunting_yard(tokens).unwrap();
+        let correct = vec![
+            Number(1.into()),
+            Number(1.into()),
+            Op(Plus),
+            Number(3.into()),
+            Op(Less),
+            Op(Not),
+            Number(0.into()),
+            Op(And),
+        ];
+        assert_eq!(res, correct);
+    }
+
+    #[test]
+    fn postfix_operators_go_straight_to_output() {
+        // -2 ^ 3! + 50%
+        let tokens = vec![
+            Op(Minus),
+            Number(2.into()),
+            Op(Pow),
+            Number(3.into()),
+            Op(Factorial),
+            Op(Plus),
+            Number(50.into()),
+            Op(Mod),
+        ];
+        let res = shunting_yard(tokens).unwrap();
+        let correct = vec![
+            Number(2.into()),
+            Number(3.into()),
+            Op(Factorial),
+            Op(Pow),
+            Op(Neg),
+            Number(50.into()),
+            Op(Percent),
+            Op(Plus),
+        ];
+        assert_eq!(res, correct);
+    }
+
+    #[test]
+    fn if_becomes_jumps() {
+        // if(1, 2, 3 + 4) * 5
+        let tokens = vec![
+            Op(Func(Function::If, 0)),
+            Op(Lparen),
+            Number(1.into()),
+            Op(Comma),
+            Number(2.into()),
+            Op(Comma),
+            Number(3.into()),
+            Op(Plus),
+            Number(4.into()),
+            Op(Rparen),
+            Op(Mul),
+            Number(5.into()),
+        ];
+        let res = shunting_yard(tokens).unwrap();
+        let correct = vec![
+            Number(1.into()),
+            JumpIfFalse(4),
+            Number(2.into()),
+            Jump(7),
+            Number(3.into()),
+            Number(4.into()),
+            Op(Plus),
+            Number(5.into()),
+            Op(Mul),
+        ];
+        assert_eq!(res, correct);
+    }
+
+    #[test]
+    fn modulo_and_floor_division_group_like_division() {
+        // 7 // 2 % 3 * 4
+        let tokens = vec![
+            Number(7.into()),
+            Op(FloorDiv),
+            Number(2.into()),
+            Op(Mod),
+            Number(3.into()),
+            Op(Mul),
+            Number(4.into()),
+        ];
+        let res = shunting_yard(tokens).unwrap();
+        let correct = vec![
+            Number(7.into()),
+            Number(2.into()),
+            Op(FloorDiv),
+            Number(3.into()),
+            Op(Mod),
+            Number(4.into()),
+            Op(Mul),
+        ];
+        assert_eq!(res, correct);
+    }
+
+    #[test]
+    fn equal_precedence_groups_by_associativity() {
+        // for every pair of operators, `8 a 4 b 2` should be either `(8 a 4) b 2`
+        // or `8 a (4 b 2)`
+        let ops = [Plus, Minus, Mul, Div, Pow];
+        let grouped_left = [
+            // second:   +     -      *      /      ^
+            /* + */ [true, true, false, false, false],
+            /* - */ [true, true, false, false, false],
+            /* * */ [true, true, true, true, false],
+            /* / */ [true, true, true, true, false],
+            /* ^ */ [true, true, true, true, false],
+        ];
+
+        for (i, a) in ops.iter().enumerate() {
+            for (j, b) in ops.iter().enumerate() {
+                let tokens = vec![
+                    Number(8.0.into()),
+                    Op(a.clone()),
+                    Number(4.0.into()),
+                    Op(b.clone()),
+                    Number(2.0.into()),
+                ];
+                let res = shunting_yard(tokens).unwrap();
+
+                let correct = if grouped_left[i][j] {
+                    vec![Number(8.0.into()), Number(4.0.into()), Op(a.clone()), Number(2.0.into()), Op(b.clone())]
+                } else {
+                    vec![Number(8.0.into()), Number(4.0.into()), Number(2.0.into()), Op(b.clone()), Op(a.clone())]
+                };
+                assert_eq!(res, correct, "8 {a} 4 {b} 2");
+            }
+        }
+    }
+
+    #[test]
+    fn unary_minus_is_resolved_from_context() {
+        // -2 - -a
+        let tokens = vec![
+            Op(Minus),
+            Number(2.0.into()),
+            Op(Minus),
+            Op(Minus),
+            Variable("a".to_string()),
+        ];
+        let res = shunting_yard(tokens).unwrap();
+        let correct = vec![
+            Number(2.0.into()),
+            Op(Neg),
+            Variable("a".to_string()),
+            Op(Neg),
+            Op(Minus),
+        ];
+        assert_eq!(res, correct);
+    }
+
+    #[test]
+    fn unary_minus_binds_looser_than_pow() {
+        // -2 ^ 2
+        let tokens = vec![Op(Minus), Number(2.0.into()), Op(Pow), Number(2.0.into())];
+        let res = shunting_yard(tokens).unwrap();
+        let correct = vec![Number(2.0.into()), Number(2.0.into()), Op(Pow), Op(Neg)];
+        assert_eq!(res, correct);
+
+        // 2 ^ -2
+        let tokens = vec![Number(2.0.into()), Op(Pow), Op(Minus), Number(2.0.into())];
+        let res = shunting_yard(tokens).unwrap();
+        let correct = vec![Number(2.0.into()), Number(2.0.into()), Op(Neg), Op(Pow)];
+        assert_eq!(res, correct);
+    }
+
+    #[test]
+    fn only_signs_can_be_unary() {
+        let tokens = vec![Number(1.0.into()), Op(Plus), Op(Mul), Number(2.0.into())];
+        assert!(shunting_yard(tokens).is_err());
+    }
+
+    #[test]
+    fn user_function_arguments_are_counted() {
+        // f(1, x)
+        let tokens = vec![
+            Op(UserFunc("f".to_string(), 0)),
+            Op(Lparen),
+            Number(1.0.into()),
+            Op(Comma),
+            Variable("x".to_string()),
+            Op(Rparen),
+        ];
+        let res = shunting_yard(tokens).unwrap();
+        let correct = vec![
+            Number(1.0.into()),
+            Variable("x".to_string()),
+            Op(UserFunc("f".to_string(), 2)),
+        ];
+        assert_eq!(res, correct);
+    }
+
+    #[test]
+    fn equals_sign_errors() {
+        let tokens = vec![Number(1.0.into()), Op(Equals), Number(2.0.into())];
+        assert!(shunting_yard(tokens).is_err());
+    }
+
+    #[test]
+    fn trailing_operator_errors() {
+        let tokens = vec![Number(1.0.into()), Op(Plus)];
+        assert!(shunting_yard(tokens).is_err());
+
+        let tokens = vec![Op(Minus)];
+        assert!(shunting_yard(tokens).is_err());
+    }
+
+    #[test]
+    fn spans_are_kept() {
+        // "-max(1, 2) * a"
+        let tokens = vec![
+            (Op(Minus), 0..1),
+            (Op(Func(Function::Max, 0)), 1..4),
+            (Op(Lparen), 4..5),
+            (Number(1.0.into()), 5..6),
+            (Op(Comma), 6..7),
+            (Number(2.0.into()), 8..9),
+            (Op(Rparen), 9..10),
+            (Op(Mul), 11..12),
+            (Variable("a".to_string()), 13..14),
+        ];
+        let res = shunting_yard_spanned(tokens).unwrap();
+        let correct = vec![
+            (Number(1.0.into()), 5..6),
+            (Number(2.0.into()), 8..9),
+            (Op(Func(Function::Max, 2)), 1..10),
+            (Op(Neg), 0..1),
+            (Variable("a".to_string()), 13..14),
+            (Op(Mul), 11..12),
+        ];
+        assert_eq!(res, correct);
+    }
+
+    #[test]
+    fn error_spans_point_at_the_problem() {
+        // "(1 + 2"
+        let tokens = vec![
+            (Op(Lparen), 0..1),
+            (Number(1.0.into()), 1..2),
+            (Op(Plus), 3..4),
+            (Number(2.0.into()), 5..6),
+        ];
+        let err = shunting_yard_spanned(tokens).unwrap_err();
+        assert_eq!(err.span(), &(0..1));
+
+        // "1 2"
+        let tokens = vec![(Number(1.0.into()), 0..1), (Number(2.0.into()), 2..3)];
+        let err = shunting_yard_spanned(tokens).unwrap_err();
+        assert_eq!(err.span(), &(2..3));
+    }
 }
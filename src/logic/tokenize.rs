@@ -1,6 +1,11 @@
 use super::enums::Token::{self, *};
 use super::enums::Operator::{self, *};
 use super::enums::Function;
+use super::error::{CalcError, Span};
+use super::value::Value;
+use num_bigint::BigInt;
+use std::iter::Peekable;
+use std::str::CharIndices;
 
 /// Tokenize a string into a `Vec` of Tokens.
 ///
@@ -9,67 +14,137 @@
 /// This function does not care about order of operations (`1 + 1` is just as valid as `1 * + /`)
 /// Example:
 /// ```
+/// use tiralabra_calculator::{tokenize, Operator, Token::{Number, Op}};
+///
 /// let result = tokenize("1 + 1").unwrap();
-/// let correct = vec![Number(1), Operator('+'), Number(1)];
+/// let correct = vec![Number(1.0.into()), Op(Operator::Plus), Number(1.0.into())];
 ///
 /// assert_eq!(result, correct);
 /// ```
-pub fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+pub fn tokenize(input: &str) -> Result<Vec<Token>, CalcError> {
+    let tokens = tokenize_spanned(input)?;
+    Ok(tokens.into_iter().map(|(token, _)| token).collect())
+}
+
+/// Same as `tokenize`, but every token comes with the `Span` of the input it was read from.
+///
+/// ```
+/// use tiralabra_calculator::{tokenize_spanned, Token::{Number, Variable}};
+///
+/// let result = tokenize_spanned("12 + a").unwrap();
+/// assert_eq!(result[0], (Number(12.0.into()), 0..2));
+/// assert_eq!(result[2], (Variable("a".to_string()), 5..6));
+/// ```
+pub fn tokenize_spanned(input: &str) -> Result<Vec<(Token, Span)>, CalcError> {
     let mut output = Vec::new();
 
-    let mut chars = input.chars().peekable();
+    let mut chars = input.char_indices().peekable();
 
-    loop {
-        let c = chars.next();
-        if c.is_none() {
-            break;
+    while let Some((start, c)) = chars.next() {
+        // byte index right after `c`, extended as more characters belong to the same token
+        let mut end = start + c.len_utf8();
+
+        // if `c` is a digit (0 <= c <= 9) then find out how long the number is.
+        // Signs are not part of the number, `shunting_yard` handles `-` and `+` in front of
+        // operands. A number can also start with the decimal separator, e.g. `.5`
+        let leading_dot = c == '.' && chars.peek().is_some_and(|&(_, c)| c.is_ascii_digit());
+
+        // `0x`, `0b` and `0o` start a hexadecimal, binary or octal integer
+        let radix = chars.peek().and_then(|&(_, prefix)| get_radix(prefix));
+        if let (Some(radix), '0') = (radix, c) {
+            chars.next();
+            let (num, end) = read_radix_integer(&mut chars, start, radix)?;
+            output.push((Number(num), start..end));
+            continue;
         }
-        let c = c.unwrap();
-
-        // if c is the minus sign, two operators in a row is ok (negative number) if the following
-        // character is a digit
-        let negative_number = if c == '-' {
-            // if the previous character is an operator, or `c` is the first character of the
-            // input
-            matches!(
-                (output.last(), chars.peek()),
-                (Some(Op(_)) | None, Some('0'..='9'))
-            )
-        } else {
-            false
-        };
 
-        // if `c` is a digit (0 <= c <= 9) then find out how long the number is
-        if c.is_digit(10) || negative_number {
+        if c.is_ascii_digit() || leading_dot {
             let mut num_string = String::new();
             num_string.push(c);
-            let mut found_decimal = false;
+            let mut found_decimal = leading_dot;
 
             // if the current number is more than one digit (e.g. 13),
             // need to loop to find the end
-            while let Some(&c) = chars.peek() {
-                if c.is_digit(10) {
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_ascii_digit() {
                     num_string.push(c);
+                } else if c == '_' {
+                    // a digit separator, as in `1_000_000`
+                    let after_digit = num_string.ends_with(|c: char| c.is_ascii_digit());
+                    if !after_digit || !next_is_digit(&chars, 10) {
+                        return Err(separator_error(i));
+                    }
                 } else if c == '.' {
                     if found_decimal {
-                        return Err(format!("Too many decimal separators: {num_string}."))
+                        return Err(CalcError::Lexical {
+                            message: format!("Too many decimal separators: {num_string}."),
+                            span: start..i + 1,
+                        });
                     }
                     num_string.push(c);
                     found_decimal = true;
                 } else {
                     break;
                 }
+                end = i + c.len_utf8();
                 chars.next();
             }
 
-            let &num = &num_string.parse::<f64>().unwrap();
-            output.push(Number(num));
+            // an exponent, e.g. `1e-9`. The `e` has to be followed by digits (and optionally a
+            // sign), otherwise it's a name: `2e` is 2 and the variable `e`
+            if let Some(exponent_len) = exponent_length(&chars) {
+                for _ in 0..exponent_len {
+                    let (i, c) = chars.next().expect("exponent_length only counts existing chars");
+                    num_string.push(c);
+                    end = i + 1;
+                }
+                if let Some(&(i, '.')) = chars.peek() {
+                    return Err(CalcError::Lexical {
+                        message: format!("The exponent has to be an integer: {num_string}."),
+                        span: start..i + 1,
+                    });
+                }
+            }
+
+            let num = Value::from_decimal(&num_string).ok_or_else(|| CalcError::Lexical {
+                message: format!("Invalid number: {num_string}"),
+                span: start..end,
+            })?;
+
+            // an `i` right after the number makes it imaginary (`4i`), unless it's the start of
+            // a longer name. The `i` is a token of its own, multiplied by the number in
+            // `implicit_multiplication`, so that `2i^2` is `2 * i^2` like `2x^2` is `2 * x^2`.
+            // It isn't a `Variable`, so a parameter called `i` doesn't change its meaning
+            let mut imaginary_unit = None;
+            if let Some(&(i, 'i')) = chars.peek() {
+                let mut rest = chars.clone();
+                rest.next();
+                if !rest.peek().is_some_and(|&(_, c)| c.is_alphanumeric() || c == '_') {
+                    if !num.to_f64().is_finite() {
+                        return Err(CalcError::Lexical {
+                            message: format!("The imaginary number is too large: {num_string}i"),
+                            span: start..i + 1,
+                        });
+                    }
+                    imaginary_unit = Some((ImaginaryUnit, i..i + 1));
+                    chars.next();
+                }
+            }
+            output.push((Number(num), start..end));
+            output.extend(imaginary_unit);
+            continue;
+        }
+
+        // `//`, `<<` and `>>` have to be checked before `/`
+        if let Some(op) = chars.peek().and_then(|&(_, next)| get_double_operator(c, next)) {
+            chars.next();
+            output.push((Op(op), start..end + 1));
             continue;
         }
 
         // handle case of `c` being one of '+', '/', etc.
         if let Some(op) = get_operator(c) {
-            output.push(Op(op));
+            output.push((Op(op), start..end));
             continue;
         }
 
@@ -78,16 +153,40 @@ pub fn tokenize(input: &str) -> Result<Vec<Token>, String> {
             continue;
         }
 
-        // if c is a letter, it can either be a variable name or a function
+        // `$` followed by a number refers to an earlier result, e.g. `$3`
+        if c == '$' {
+            let mut num_string = String::new();
+            while let Some(&(i, c)) = chars.peek() {
+                if !c.is_ascii_digit() {
+                    break;
+                }
+                num_string.push(c);
+                end = i + 1;
+                chars.next();
+            }
+
+            match num_string.parse::<usize>() {
+                Ok(n) => output.push((History(n), start..end)),
+                Err(_) => {
+                    return Err(CalcError::Lexical {
+                        message: format!("Expected a result number after '$': ${num_string}"),
+                        span: start..end,
+                    });
+                }
+            }
+            continue;
+        }
+
+        // if c is a letter (or an underscore), it can either be a variable name or a function
         // if the following character is a left parenthesis, it's a function
-        if c.is_alphabetic() {
+        if c.is_alphabetic() || c == '_' {
             let mut var_string = String::new();
             var_string.push(c);
 
             let mut is_function = false;
             let mut found_whitespace = false;
-            while let Some(&c) = chars.peek() {
-                if c.is_alphanumeric() {
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_alphanumeric() || c == '_' {
 
                     // 1 + a b + 2 is not valid syntax (`a b` is two variables after each other) 
                     // have to check here (instead of just forbidding all whitespace) because a
@@ -97,6 +196,7 @@ pub fn tokenize(input: &str) -> Result<Vec<Token>, String> {
                         break;
                     }
                     var_string.push(c);
+                    end = i + c.len_utf8();
                 } else if c.is_whitespace() {
                     found_whitespace = true;
                 } else if c == '(' {
@@ -107,35 +207,130 @@ pub fn tokenize(input: &str) -> Result<Vec<Token>, String> {
                 }
                 chars.next();
             }
-            if is_function {
+            if let (Some(op), false) = (get_word_operator(&var_string), is_function) {
+                output.push((Op(op), start..end));
+            } else if is_function {
 
+                // anything that isn't built-in may be defined by the user,
+                // which is checked only when the function is called
                 if let Some(fun) = get_function(&var_string) {
-                    output.push(Op(Func(fun)));
+                    output.push((Op(Func(fun, 0)), start..end));
                 } else {
-                    return Err(format!("Unknown function: {var_string}"));
+                    output.push((Op(UserFunc(var_string, 0)), start..end));
                 }
             } else {
-                output.push(Variable(var_string));
+                output.push((Variable(var_string), start..end));
             }
             continue;
         }
 
         if c == '=' {
-            output.push(Op(Operator::Equals));
+            output.push((Op(Operator::Equals), start..end));
             continue;
         }
-        return Err(format!("Unknown character, or incorrectly placed: {c}"));
+        return Err(CalcError::Lexical {
+            message: format!("Unknown character, or incorrectly placed: {c}"),
+            span: start..end,
+        });
     }
     Ok(output)
 }
 
-/// Return an `Operator` enum if `c` is one of the defined mathematical operators
+/// Reads the digits of a `0x`, `0b` or `0o` integer, after the prefix. `start` is where the
+/// prefix starts. Returns the number and the end of its span.
+///
+/// Anything that looks like a digit but isn't one in `radix` is an error, e.g. `0b102`.
+fn read_radix_integer(
+    chars: &mut Peekable<CharIndices>,
+    start: usize,
+    radix: u32,
+) -> Result<(Value, usize), CalcError> {
+    let mut digits = String::new();
+    let mut end = start + 2;
+    while let Some(&(i, c)) = chars.peek() {
+        if c.is_digit(radix) {
+            digits.push(c);
+        } else if c == '_' {
+            if digits.is_empty() || !next_is_digit(chars, radix) {
+                return Err(separator_error(i));
+            }
+        } else if c.is_alphanumeric() || c == '.' {
+            return Err(CalcError::Lexical {
+                message: format!("Invalid digit for a base {radix} number: {c}"),
+                span: i..i + c.len_utf8(),
+            });
+        } else {
+            break;
+        }
+        end = i + c.len_utf8();
+        chars.next();
+    }
+
+    // the digits are all valid, so only an empty number can fail
+    match BigInt::parse_bytes(digits.as_bytes(), radix) {
+        Some(num) => Ok((Value::Int(num), end)),
+        None => Err(CalcError::Lexical {
+            message: "Expected digits after the prefix".to_string(),
+            span: start..end,
+        }),
+    }
+}
+
+/// Whether the character after the next one is a digit in `radix`. Used to check that the
+/// next character, a digit separator, is followed by a digit.
+fn next_is_digit(chars: &Peekable<CharIndices>, radix: u32) -> bool {
+    let mut rest = chars.clone();
+    rest.next();
+    rest.peek().is_some_and(|&(_, c)| c.is_digit(radix))
+}
+
+fn separator_error(i: usize) -> CalcError {
+    CalcError::Lexical {
+        message: "A digit separator ('_') has to be between two digits".to_string(),
+        span: i..i + 1,
+    }
+}
+
+/// The base of an integer with the prefix `0` followed by `c`:
+///
+/// ```text
+/// get_radix('x') -> Some(16)
+/// get_radix('5') -> None
 /// ```
-/// let op = get_operator('+').unwrap();
-/// assert_eq!(op, Operator::Plus);
+fn get_radix(c: char) -> Option<u32> {
+    match c {
+        'x' | 'X' => Some(16),
+        'b' | 'B' => Some(2),
+        'o' | 'O' => Some(8),
+        _ => None,
+    }
+}
+
+/// If the next characters are an exponent (`e3`, `E-9`, `e+12`, ...), returns how many
+/// characters long it is.
 ///
-/// let op = get_operator('a');
-/// assert_eq!(op, None);
+/// ```text
+/// exponent_length("e-9 + 1") -> Some(3)
+/// exponent_length("e + 1")   -> None
+/// ```
+fn exponent_length(chars: &Peekable<CharIndices>) -> Option<usize> {
+    let mut chars = chars.clone().map(|(_, c)| c).peekable();
+    if !matches!(chars.next(), Some('e' | 'E')) {
+        return None;
+    }
+    let sign = usize::from(chars.next_if(|&c| c == '+' || c == '-').is_some());
+    let digits = chars.take_while(char::is_ascii_digit).count();
+    if digits == 0 {
+        None
+    } else {
+        Some(1 + sign + digits)
+    }
+}
+
+/// Return an `Operator` enum if `c` is one of the defined mathematical operators
+/// ```text
+/// get_operator('+') -> Some(Operator::Plus)
+/// get_operator('a') -> None
 /// ```
 fn get_operator(c: char) -> Option<Operator> {
     use self::Operator::*;
@@ -147,28 +342,56 @@ fn get_operator(c: char) -> Option<Operator> {
         '^' => Some(Pow),
         '(' => Some(Lparen),
         ')' => Some(Rparen),
+        ',' => Some(Comma),
+        '%' => Some(Mod),
+        '&' => Some(BitAnd),
+        '|' => Some(BitOr),
+        '<' => Some(Less),
+        '>' => Some(Greater),
+        '!' => Some(Factorial),
+        '°' => Some(Degree),
         _ => None
     }
 }
 
-/// Input is a `&str`, returns a Function enum:
-///
+/// Return the operator made of the two characters `c` and `next`, if there is one
+/// ```text
+/// get_double_operator('/', '/') -> Some(Operator::FloorDiv)
+/// get_double_operator('/', '2') -> None
 /// ```
-/// let fun = get_function("sin").unwrap();
-/// assert_eq!(fun, Function::Sin);
+fn get_double_operator(c: char, next: char) -> Option<Operator> {
+    match (c, next) {
+        ('/', '/') => Some(FloorDiv),
+        ('<', '<') => Some(Shl),
+        ('>', '>') => Some(Shr),
+        ('<', '=') => Some(LessEqual),
+        ('>', '=') => Some(GreaterEqual),
+        ('=', '=') => Some(EqualTo),
+        ('!', '=') => Some(NotEqual),
+        _ => None,
+    }
+}
+
+/// Return the operator written as the word `s`, e.g. `xor`
+fn get_word_operator(s: &str) -> Option<Operator> {
+    match s {
+        "xor" => Some(BitXor),
+        "and" => Some(And),
+        "or" => Some(Or),
+        "not" => Some(Not),
+        _ => None,
+    }
+}
+
+/// Input is a `&str`, returns a Function enum:
 ///
-/// let fun = get_function("The answer to life, the universe, and everything");
-/// assert_eq!(fun, None);
+/// ```text
+/// get_function("sin") -> Some(Function::Sin)
+/// get_function("The answer to life, the universe, and everything") -> None
 /// ```
 fn get_function(s: &str) -> Option<Function> {
-    use self::Function::*;
-    match &*s.to_lowercase() {
-        "sin" => Some(Sin),
-        "cos" => Some(Cos),
-        "tan" => Some(Tan),
-        "sqrt" => Some(Sqrt),
-        _ => None
-    }
+    let name = s.to_lowercase();
+    Function::all().find(|fun| fun.format() == name)
 }
 
 #[cfg(test)]
@@ -180,7 +403,7 @@ fn simple_case() {
         let test_str = "1";
         let result = tokenize(test_str).unwrap();
 
-        assert_eq!(result, vec![Number(1.0)]);
+        assert_eq!(result, vec![Number(1.0.into())]);
     }
 
     #[test]
@@ -188,7 +411,23 @@ fn one_plus_times_div() {
         let test_str = "1 + * /";
         let result = tokenize(test_str).unwrap();
 
-        let correct = vec![Number(1.0), Op(Plus), Op(Mul), Op(Div)];
+        let correct = vec![Number(1.0.into()), Op(Plus), Op(Mul), Op(Div)];
+
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn minus_sign_is_an_operator() {
+        let test_str = "-1 * -a";
+        let result = tokenize(test_str).unwrap();
+
+        let correct = vec![
+            Op(Minus),
+            Number(1.0.into()),
+            Op(Mul),
+            Op(Minus),
+            Variable("a".to_string()),
+        ];
 
         assert_eq!(result, correct);
     }
@@ -198,7 +437,7 @@ fn decimal_numbers_parsed() {
         let test_str = "1.5";
         let result = tokenize(test_str).unwrap();
 
-        assert_eq!(result, vec![Number(1.5)]);
+        assert_eq!(result, vec![Number(1.5.into())]);
     }
 
     #[test]
@@ -206,7 +445,7 @@ fn variables() {
         let test_str = "a + 1";
         let result = tokenize(test_str).unwrap();
 
-        let correct = vec![Variable("a".to_string()), Op(Plus), Number(1.0)];
+        let correct = vec![Variable("a".to_string()), Op(Plus), Number(1.0.into())];
 
         assert_eq!(result, correct);
     }
@@ -219,6 +458,67 @@ fn multi_letter_variables() {
         assert_eq!(result[0], Variable(test_str.to_string()));
     }
 
+    #[test]
+    fn function_with_arguments() {
+        let test_str = "min(1, a)";
+        let result = tokenize(test_str).unwrap();
+
+        let correct = vec![
+            Op(Func(Function::Min, 0)),
+            Op(Lparen),
+            Number(1.0.into()),
+            Op(Comma),
+            Variable("a".to_string()),
+            Op(Rparen),
+        ];
+
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn underscores_in_variables() {
+        let test_str = "_ + my_var";
+        let result = tokenize(test_str).unwrap();
+
+        let correct = vec![
+            Variable("_".to_string()),
+            Op(Plus),
+            Variable("my_var".to_string()),
+        ];
+
+        assert_eq!(result, correct);
+    }
+
+    #[test]
+    fn history_references() {
+        let test_str = "$3 * $12";
+        let result = tokenize(test_str).unwrap();
+
+        assert_eq!(result, vec![History(3), Op(Mul), History(12)]);
+    }
+
+    #[test]
+    fn dollar_without_number() {
+        let result = tokenize("$ + 1");
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn user_function_call() {
+        let test_str = "f (x)";
+        let result = tokenize(test_str).unwrap();
+
+        let correct = vec![
+            Op(UserFunc("f".to_string(), 0)),
+            Op(Lparen),
+            Variable("x".to_string()),
+            Op(Rparen),
+        ];
+
+        assert_eq!(result, correct);
+    }
+
     #[test]
     fn unknown_character() {
         let test_str = "¦ + 1";
@@ -226,6 +526,195 @@ fn unknown_character() {
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn spans_point_at_the_input() {
+        let test_str = "sin (x1) + 2.5 * $3";
+        let result = tokenize_spanned(test_str).unwrap();
+
+        let spans: Vec<Span> = result.into_iter().map(|(_, span)| span).collect();
+        let correct = vec![0..3, 4..5, 5..7, 7..8, 9..10, 11..14, 15..16, 17..19];
+
+        assert_eq!(spans, correct);
+    }
+
+    #[test]
+    fn error_spans_point_at_the_input() {
+        let err = tokenize("1 + ¦").unwrap_err();
+        assert_eq!(err.span(), &(4..6));
+
+        let err = tokenize("1.2.3").unwrap_err();
+        assert_eq!(err.span(), &(0..4));
+    }
+
+    #[test]
+    fn scientific_notation() {
+        let res = tokenize("6.022e23 * 1E-9").unwrap();
+        let correct = vec![
+            Number(Value::from_decimal("602200000000000000000000").unwrap()),
+            Op(Mul),
+            Number(Value::from_decimal("0.000000001").unwrap()),
+        ];
+        assert_eq!(res, correct);
+
+        assert_eq!(tokenize("2e3").unwrap(), vec![Number(2000.0.into())]);
+        assert_eq!(tokenize("2e+3").unwrap(), vec![Number(2000.0.into())]);
+        assert_eq!(tokenize_spanned("1.5e-3 ").unwrap()[0].1, 0..6);
+    }
+
+    #[test]
+    fn e_without_digits_is_a_name() {
+        let e = || Variable("e".to_string());
+        assert_eq!(tokenize("2e").unwrap(), vec![Number(2.0.into()), e()]);
+        assert_eq!(tokenize("2e+1").unwrap(), vec![Number(20.0.into())]);
+        let res = tokenize("2e +1").unwrap();
+        assert_eq!(res, vec![Number(2.0.into()), e(), Op(Plus), Number(1.0.into())]);
+        let res = tokenize("2e+x").unwrap();
+        assert_eq!(res, vec![Number(2.0.into()), e(), Op(Plus), Variable("x".to_string())]);
+        let res = tokenize("2exp").unwrap();
+        assert_eq!(res, vec![Number(2.0.into()), Variable("exp".to_string())]);
+    }
+
+    #[test]
+    fn leading_decimal_separator() {
+        assert_eq!(tokenize(".5").unwrap(), vec![Number(0.5.into())]);
+        let res = tokenize("1+.25e1").unwrap();
+        assert_eq!(res, vec![Number(1.0.into()), Op(Plus), Number(2.5.into())]);
+
+        let err = tokenize("1 + .").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown character, or incorrectly placed: .");
+        let err = tokenize(".5.5").unwrap_err();
+        assert_eq!(err.span(), &(0..3));
+    }
+
+    #[test]
+    fn exponents_must_be_integers() {
+        let err = tokenize("1e3.5").unwrap_err();
+        assert_eq!(err.to_string(), "The exponent has to be an integer: 1e3.");
+        assert_eq!(err.span(), &(0..4));
+    }
+
+    #[test]
+    fn other_bases() {
+        let res = tokenize_spanned("0xFF + 0b1010 - 0o755").unwrap();
+        assert_eq!(res[0], (Number(255.0.into()), 0..4));
+        assert_eq!(res[2], (Number(10.0.into()), 7..13));
+        assert_eq!(res[4], (Number(493.0.into()), 16..21));
+
+        assert_eq!(tokenize("0XdeadBEEF").unwrap(), vec![Number(3735928559.0.into())]);
+        assert_eq!(tokenize("0b1111_0000").unwrap(), vec![Number(240.0.into())]);
+    }
+
+    #[test]
+    fn invalid_digits_error() {
+        let err = tokenize("0b102").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid digit for a base 2 number: 2");
+        assert_eq!(err.span(), &(4..5));
+
+        let err = tokenize("1 + 0o8").unwrap_err();
+        assert_eq!(err.span(), &(6..7));
+        assert!(tokenize("0xFG").is_err());
+        assert!(tokenize("0x1.8").is_err());
+
+        let err = tokenize("0x + 1").unwrap_err();
+        assert_eq!(err.to_string(), "Expected digits after the prefix");
+        assert_eq!(err.span(), &(0..2));
+    }
+
+    #[test]
+    fn digit_separators() {
+        assert_eq!(tokenize("1_000_000").unwrap(), vec![Number(1e6.into())]);
+        assert_eq!(tokenize("1_0.2_5").unwrap(), vec![Number(10.25.into())]);
+
+        for input in ["1__0", "1_", "1_.5", "1._5", "0x_F", "0b1_"] {
+            let err = tokenize(input).unwrap_err();
+            assert_eq!(err.to_string(), "A digit separator ('_') has to be between two digits");
+        }
+        // not a number at all
+        assert_eq!(tokenize("_1").unwrap(), vec![Variable("_1".to_string())]);
+    }
+
+    #[test]
+    fn malformed_numbers_dont_panic() {
+        let inputs = [
+            "1..2", "1.2.3", ".", "1.e", "0x", "0b", "0o_", "1e", "1e+", "1e-x", "1e1.5",
+            "00.00", "1_e5", "0b2", "1.5i.5", "$1.5", "1e99999999999999999999",
+        ];
+        for input in inputs {
+            // errors are fine, as long as there is no panic
+            let _ = tokenize(input);
+        }
+        assert!(tokenize(&"9".repeat(1000)).is_ok());
+    }
+
+    #[test]
+    fn integer_operators() {
+        let res = tokenize_spanned("7 // 2 % 3 << 1").unwrap();
+        let operators: Vec<_> =
+            res.into_iter().filter(|(token, _)| matches!(token, Op(_))).collect();
+        assert_eq!(operators, vec![(Op(FloorDiv), 2..4), (Op(Mod), 7..8), (Op(Shl), 11..13)]);
+
+        let res = tokenize("a&b|c xor d>>1").unwrap();
+        let correct = vec![
+            Variable("a".to_string()),
+            Op(BitAnd),
+            Variable("b".to_string()),
+            Op(BitOr),
+            Variable("c".to_string()),
+            Op(BitXor),
+            Variable("d".to_string()),
+            Op(Shr),
+            Number(1.0.into()),
+        ];
+        assert_eq!(res, correct);
+
+        // only the whole word is an operator
+        assert_eq!(tokenize("xored").unwrap(), vec![Variable("xored".to_string())]);
+    }
+
+    #[test]
+    fn comparison_and_logical_operators() {
+        let res = tokenize("a<=1 and not b!=2 or c==d").unwrap();
+        let operators: Vec<_> = res.into_iter().filter(|token| matches!(token, Op(_))).collect();
+        let correct = [LessEqual, And, Not, NotEqual, Or, EqualTo].map(Op);
+        assert_eq!(operators, correct);
+
+        // `=` is still assignment
+        let res = tokenize_spanned("x = 1 < 2").unwrap();
+        assert_eq!(res[1], (Op(Equals), 2..3));
+        assert_eq!(res[3], (Op(Less), 6..7));
+        assert_eq!(tokenize("5!").unwrap(), vec![Number(5.into()), Op(Factorial)]);
+    }
+
+    #[test]
+    fn imaginary_numbers() {
+        // the number and `i` are multiplied later, in `implicit_multiplication`
+        let res = tokenize_spanned("3 + 4i").unwrap();
+        assert_eq!(res[2], (Number(4.0.into()), 4..5));
+        assert_eq!(res[3], (ImaginaryUnit, 5..6));
+
+        // only an `i` written right after the number is part of it
+        let res = tokenize("2.5i * i").unwrap();
+        let correct = vec![
+            Number(Value::from_decimal("2.5").unwrap()),
+            ImaginaryUnit,
+            Op(Mul),
+            Variable("i".to_string()),
+        ];
+        assert_eq!(res, correct);
+        assert_eq!(tokenize("4 i").unwrap(), vec![Number(4.into()), Variable("i".to_string())]);
+
+        // not an imaginary number, but a number followed by a name
+        let res = tokenize("2if").unwrap();
+        assert_eq!(res, vec![Number(2.0.into()), Variable("if".to_string())]);
+    }
+
+    #[test]
+    fn infinite_imaginary_numbers_are_rejected() {
+        let err = tokenize_spanned("1 + 1e400i").unwrap_err();
+        assert_eq!(err.to_string(), "The imaginary number is too large: 1e400i");
+        assert_eq!(err.span(), &(4..10));
+    }
 }
 
 #[cfg(test)]
@@ -234,7 +723,7 @@ mod is_operator_tests {
 
     #[test]
     fn returns_true_for_operators() {
-        let operators = ['+', '-', '*', '/', '^', '(', ')'];
+        let operators = ['+', '-', '*', '/', '^', '(', ')', ',', '%', '&', '|'];
 
         for operator in operators {
             assert!(get_operator(operator).is_some());
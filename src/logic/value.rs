@@ -0,0 +1,354 @@
+use super::format::complex_to_string;
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::{FromPrimitive, ToPrimitive, Zero};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A number in a calculation.
+///
+/// Numbers written in the input are always read exactly: whole numbers as `Int` and others as
+/// `Rational`, so that `0.1` really is one tenth. Integers stay exact as long as only integer
+/// operations are done on them, whether fractions stay exact depends on the calculator's
+/// `NumberMode`.
+///
+/// A `Rational` is never a whole number, those are always stored as `Int`, and a `Complex` always
+/// has an imaginary part, otherwise it would be a `Float`.
+///
+/// Values are compared by the number they represent, so `Rational(3/2)` equals `Float(1.5)`.
+///
+/// ```
+/// use tiralabra_calculator::Value;
+///
+/// let half = Value::from(0.5);
+/// assert_eq!(half.to_string(), "0.5");
+/// assert_eq!(half.to_f64(), 0.5);
+/// ```
+#[derive(Debug, Clone)]
+pub enum Value {
+    /// An arbitrary-size integer
+    Int(BigInt),
+    /// An exact fraction of two arbitrary-size integers
+    Rational(BigRational),
+    Float(f64),
+    /// A complex number, with the real and the imaginary part as floats
+    Complex(Complex64),
+}
+
+/// The largest power of ten a number literal can have and still be read exactly
+const MAX_EXACT_EXPONENT: i64 = 10_000;
+
+impl Value {
+    /// Reads a decimal number like "12.25" or "1.5e-3" into an exact value (here 49/4 and
+    /// 3/2000). Returns `None` if `s` isn't made of digits and at most one '.', optionally
+    /// followed by an exponent.
+    ///
+    /// Numbers with very large exponents are read as floats, so that `1e999999999` doesn't
+    /// need a billion digits.
+    pub(crate) fn from_decimal(s: &str) -> Option<Value> {
+        let (mantissa, exponent) = match s.split_once(['e', 'E']) {
+            Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+            None => (s, None),
+        };
+        let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return None;
+        }
+
+        let digits = format!("{whole}{fraction}");
+        if !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        // the number is `digits * 10^scale`
+        let scale = match exponent {
+            Some(exponent) => {
+                let unsigned = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+                if unsigned.is_empty() || !unsigned.chars().all(|c| c.is_ascii_digit()) {
+                    return None;
+                }
+                exponent.parse::<i64>().ok()
+            }
+            None => Some(0),
+        }
+        .and_then(|exponent| exponent.checked_sub(fraction.len() as i64))
+        .filter(|scale| scale.abs() <= MAX_EXACT_EXPONENT);
+
+        let Some(scale) = scale else {
+            return s.parse().ok().map(Value::Float);
+        };
+        let numer: BigInt = digits.parse().ok()?;
+        let power = BigInt::from(10).pow(scale.unsigned_abs() as u32);
+        if scale >= 0 {
+            Some(Value::Int(numer * power))
+        } else {
+            Some(BigRational::new(numer, power).into())
+        }
+    }
+
+    /// The value as a float, rounding if needed
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Value::Int(n) => n.to_f64().unwrap_or(f64::NAN),
+            Value::Rational(r) => r.to_f64().unwrap_or(f64::NAN),
+            Value::Float(f) => *f,
+            Value::Complex(_) => f64::NAN,
+        }
+    }
+
+    /// The value as a complex number (with a zero imaginary part, if it's real)
+    pub fn to_complex(&self) -> Complex64 {
+        match self {
+            Value::Complex(c) => *c,
+            _ => Complex64::new(self.to_f64(), 0.0),
+        }
+    }
+
+    pub fn is_complex(&self) -> bool {
+        matches!(self, Value::Complex(_))
+    }
+
+    /// Whether the value is stored exactly (not as a float)
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Value::Int(_) | Value::Rational(_))
+    }
+
+    /// The value as an exact fraction, or `None` for floats
+    pub fn to_rational(&self) -> Option<BigRational> {
+        match self {
+            Value::Int(n) => Some(BigRational::from_integer(n.clone())),
+            Value::Rational(r) => Some(r.clone()),
+            Value::Float(_) | Value::Complex(_) => None,
+        }
+    }
+
+    /// The value as an integer, if it is one. Floats with no fractional part count too.
+    pub fn to_integer(&self) -> Option<BigInt> {
+        match self {
+            Value::Int(n) => Some(n.clone()),
+            Value::Rational(_) => None,
+            Value::Float(f) if f.fract() == 0.0 => BigInt::from_f64(*f),
+            Value::Float(_) | Value::Complex(_) => None,
+        }
+    }
+
+    /// The value converted to a `Float`
+    pub fn to_float(&self) -> Value {
+        Value::Float(self.to_f64())
+    }
+
+    /// The value as a truth value: zero is false and everything else is true.
+    /// `NaN` is neither, so it gives `None`.
+    pub fn to_bool(&self) -> Option<bool> {
+        match self {
+            Value::Int(n) => Some(!n.is_zero()),
+            Value::Rational(_) | Value::Complex(_) => Some(true),
+            Value::Float(f) if f.is_nan() => None,
+            Value::Float(f) => Some(*f != 0.0),
+        }
+    }
+
+    /// A truth value as a number, 1 for true and 0 for false
+    pub fn from_bool(b: bool) -> Value {
+        Value::from(b as i64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Value {
+        Value::Float(f)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Value {
+        Value::Int(BigInt::from(n))
+    }
+}
+
+impl From<BigInt> for Value {
+    fn from(n: BigInt) -> Value {
+        Value::Int(n)
+    }
+}
+
+/// Whole numbers become `Int`s
+impl From<BigRational> for Value {
+    fn from(r: BigRational) -> Value {
+        if r.is_integer() {
+            Value::Int(r.to_integer())
+        } else {
+            Value::Rational(r)
+        }
+    }
+}
+
+/// Complex numbers without an imaginary part become `Float`s
+impl From<Complex64> for Value {
+    fn from(c: Complex64) -> Value {
+        if c.im == 0.0 {
+            Value::Float(c.re)
+        } else {
+            Value::Complex(c)
+        }
+    }
+}
+
+impl std::ops::Neg for Value {
+    type Output = Value;
+
+    fn neg(self) -> Value {
+        match self {
+            Value::Int(n) => Value::Int(-n),
+            Value::Rational(r) => Value::Rational(-r),
+            Value::Float(f) => Value::Float(-f),
+            Value::Complex(c) => Value::Complex(-c),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialEq<f64> for Value {
+    fn eq(&self, other: &f64) -> bool {
+        *self == Value::Float(*other)
+    }
+}
+
+/// Exact values are compared exactly, also to floats: the float 0.1 is not exactly one tenth.
+/// NaN can't be compared to anything, and complex numbers can only be equal or not.
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Complex(a), Value::Complex(b)) if a == b => Some(Ordering::Equal),
+            (Value::Complex(_), _) | (_, Value::Complex(_)) => None,
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+            (Value::Float(f), exact) => {
+                compare_float(*f, &exact.to_rational()?).map(Ordering::reverse)
+            }
+            (exact, Value::Float(f)) => compare_float(*f, &exact.to_rational()?),
+            (a, b) => Some(a.to_rational()?.cmp(&b.to_rational()?)),
+        }
+    }
+}
+
+/// Compares `r` to `f`. Infinities are larger (or smaller) than any fraction.
+fn compare_float(f: f64, r: &BigRational) -> Option<Ordering> {
+    match BigRational::from_float(f) {
+        Some(f) => Some(r.cmp(&f)),
+        None if f.is_nan() => None,
+        None if f > 0.0 => Some(Ordering::Less),
+        None => Some(Ordering::Greater),
+    }
+}
+
+/// Rationals are shown as fractions (`-1/3`), complex numbers as `a + bi`, integers and floats
+/// the usual way.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{n}"),
+            Value::Rational(r) => write!(f, "{}/{}", r.numer(), r.denom()),
+            Value::Float(x) => write!(f, "{x}"),
+            Value::Complex(c) => write!(f, "{}", complex_to_string(c.re, c.im, |x| x.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod value_tests {
+    use super::*;
+
+    fn fraction(numer: i64, denom: i64) -> Value {
+        BigRational::new(BigInt::from(numer), BigInt::from(denom)).into()
+    }
+
+    #[test]
+    fn decimals_are_read_exactly() {
+        assert_eq!(Value::from_decimal("12.25"), Some(fraction(49, 4)));
+        assert_eq!(Value::from_decimal("0.1"), Some(fraction(1, 10)));
+        assert_eq!(Value::from_decimal("7"), Some(Value::from(7)));
+        assert_eq!(Value::from_decimal("3."), Some(Value::from(3)));
+        assert_eq!(Value::from_decimal("2.50"), Some(fraction(5, 2)));
+        assert_eq!(Value::from_decimal(".5"), Some(fraction(1, 2)));
+    }
+
+    #[test]
+    fn exponents_are_read_exactly() {
+        assert_eq!(Value::from_decimal("1.5e-3"), Some(fraction(3, 2000)));
+        assert_eq!(Value::from_decimal("2E+3"), Some(Value::from(2000)));
+        assert_eq!(Value::from_decimal("25e-1"), Some(fraction(5, 2)));
+        assert_eq!(Value::from_decimal("6.022e23").unwrap().to_f64(), 6.022e23);
+        assert_eq!(Value::from_decimal("1e99999"), Some(Value::Float(f64::INFINITY)));
+        assert_eq!(Value::from_decimal("1e-99999"), Some(Value::Float(0.0)));
+    }
+
+    #[test]
+    fn whole_numbers_are_integers() {
+        assert!(matches!(fraction(10, 5), Value::Int(_)));
+        assert!(matches!(Value::from_decimal("4.000"), Some(Value::Int(_))));
+        assert!(matches!(fraction(1, 3), Value::Rational(_)));
+    }
+
+    #[test]
+    fn malformed_decimals_are_rejected() {
+        assert_eq!(Value::from_decimal(""), None);
+        assert_eq!(Value::from_decimal("."), None);
+        assert_eq!(Value::from_decimal("1.2.3"), None);
+        assert_eq!(Value::from_decimal("1a"), None);
+        assert_eq!(Value::from_decimal("1e"), None);
+        assert_eq!(Value::from_decimal("1e+"), None);
+        assert_eq!(Value::from_decimal("e5"), None);
+    }
+
+    #[test]
+    fn values_are_compared_by_number() {
+        assert_eq!(fraction(3, 2), Value::from(1.5));
+        assert_eq!(Value::from(2.0), fraction(4, 2));
+        assert_ne!(fraction(1, 10), Value::from(0.1));
+        assert_ne!(Value::from(f64::NAN), Value::from(f64::NAN));
+        assert_eq!(fraction(1, 4), 0.25);
+        assert_eq!(Value::from(2), fraction(6, 3));
+    }
+
+    #[test]
+    fn values_are_ordered_by_number() {
+        assert!(fraction(1, 3) < Value::from(1));
+        assert!(Value::Float(0.1) > fraction(1, 10));
+        assert!(Value::Float(f64::INFINITY) > Value::from(10));
+        assert!(Value::Float(f64::NEG_INFINITY) < fraction(-7, 2));
+        assert_eq!(Value::from(f64::NAN).partial_cmp(&Value::from(1)), None);
+    }
+
+    #[test]
+    fn integers_from_values() {
+        assert_eq!(Value::from(12).to_integer(), Some(BigInt::from(12)));
+        assert_eq!(Value::from(-3.0).to_integer(), Some(BigInt::from(-3)));
+        assert_eq!(Value::from(2.5).to_integer(), None);
+        assert_eq!(fraction(1, 2).to_integer(), None);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(fraction(-2, 6).to_string(), "-1/3");
+        assert_eq!(fraction(10, 5).to_string(), "2");
+        assert_eq!(Value::from(0.25).to_string(), "0.25");
+        assert_eq!(Value::from(Complex64::new(3.0, 4.0)).to_string(), "3 + 4i");
+        assert_eq!(Value::from(Complex64::new(-0.5, -1.0)).to_string(), "-0.5 - i");
+        assert_eq!(Value::from(Complex64::new(0.0, -2.0)).to_string(), "-2i");
+        assert_eq!(Value::from(Complex64::new(0.0, 1.0)).to_string(), "i");
+    }
+
+    #[test]
+    fn complex_values() {
+        assert!(matches!(Value::from(Complex64::new(2.0, 0.0)), Value::Float(_)));
+        assert_eq!(Value::from(Complex64::new(1.0, 2.0)), Value::Complex(Complex64::new(1.0, 2.0)));
+        assert_ne!(Value::from(Complex64::new(1.0, 2.0)), Value::from(1));
+        assert_eq!(Value::from(3).to_complex(), Complex64::new(3.0, 0.0));
+    }
+}
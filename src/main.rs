@@ -1,8 +1,74 @@
-mod logic;
+mod args;
+mod batch;
+mod commands;
+mod helper;
+mod history;
 mod ui;
 
+use history::HistoryFile;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal};
+
 fn main() {
-    if let Err(error) = ui::main_loop() {
+    let args = match args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("Error: {err}\n\n{}", args::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", args::USAGE);
+        return;
+    }
+
+    if !args.expressions.is_empty() {
+        let lines = args.expressions.into_iter().map(Ok);
+        run_batch("-e", lines, args.keep_going);
+    }
+    if let Some(path) = args.file {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("Error: Couldn't read {}: {err}", path.display());
+                std::process::exit(2);
+            }
+        };
+        let lines = BufReader::new(file).lines();
+        let source = path.display().to_string();
+        run_batch(&source, lines, args.keep_going);
+    }
+    if !io::stdin().is_terminal() {
+        let lines = io::stdin().lock().lines();
+        run_batch("stdin", lines, args.keep_going);
+    }
+
+    let history = match HistoryFile::new(args.history, args.history_size) {
+        Ok(history) => history,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(error) = ui::main_loop(history) {
         println!("Something bad happened: {error:?}");
     }
 }
+
+/// Calculates the lines (see `batch::run`) and exits with 0 if all of them succeeded, 1 if some
+/// failed, and 2 if the input couldn't be read or the output written
+fn run_batch(
+    source: &str,
+    lines: impl IntoIterator<Item = io::Result<String>>,
+    keep_going: bool,
+) -> ! {
+    match batch::run(source, lines, keep_going, &mut io::stdout(), &mut io::stderr()) {
+        Ok(true) => std::process::exit(0),
+        Ok(false) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("Error: {err}");
+            std::process::exit(2);
+        }
+    }
+}
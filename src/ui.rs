@@ -1,19 +1,34 @@
-use crate::logic::Calculator;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::commands::{self, Reply};
+use crate::helper::CalcHelper;
+use crate::history::HistoryFile;
+use tiralabra_calculator::{Calculator, Span};
 use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use rustyline::{Config, Editor};
 
 /// The main REPL for the calculator. 
 ///
-pub fn main_loop() -> Result<(), std::io::Error> {
-    let mut calculator = Calculator::new();
-    
-    let mut rl = Editor::<()>::new();
+/// The input history is read from `history` at the start, and saved there at the end.
+pub fn main_loop(history: HistoryFile) -> Result<(), std::io::Error> {
+    // shared with the helper, which needs the variables for completions and hints
+    let calculator = Rc::new(RefCell::new(Calculator::new()));
+
+    let config = Config::builder()
+        .max_history_size(history.size)
+        .history_ignore_dups(true)
+        .build();
+    let mut rl = Editor::<CalcHelper>::with_config(config);
+    rl.set_helper(Some(CalcHelper::new(Rc::clone(&calculator))));
+    history.load(&mut rl);
+    let prompt = ">> ";
 
     let control_key = "?";
-    println!("To exit, enter {control_key}quit");
+    println!("To exit, enter {control_key}quit. For help, enter {control_key}help");
 
     loop {
-        let readline = rl.readline(">> ");
+        let readline = rl.readline(prompt);
         let input = match readline {
             Ok(line) => {
                 rl.add_history_entry(line.as_str());
@@ -32,16 +47,77 @@ pub fn main_loop() -> Result<(), std::io::Error> {
                 break;
             }
         };
+        let mut calculator = calculator.borrow_mut();
 
-        if input.starts_with(control_key) {
-            break;
+        if let Some(command) = input.trim().strip_prefix(control_key) {
+            match commands::run(&mut calculator, command) {
+                Ok(Reply::Print(lines)) => {
+                    for line in lines {
+                        println!(" {line}");
+                    }
+                }
+                Ok(Reply::Quit) => break,
+                Ok(Reply::ClearHistory) => {
+                    history.clear(&mut rl);
+                    println!(" Cleared the history");
+                }
+                Err(err) => eprintln!("Error: {err}"),
+            }
+            continue;
         }
 
-        match calculator.calculate_infix(input.trim()) {
+        match calculator.calculate_infix(&input) {
             Ok(result) => println!(" {result}"),
-            Err(err) => eprintln!("Error:\n{err}"),
+            Err(err) => {
+                eprintln!("{}", underline(&input, err.span(), prompt.len()));
+                eprintln!("Error: {err}");
+            }
         }
 
     }
+    history.save(&mut rl);
     Ok(())
 }
+
+/// Returns a line with carets under the part of `input` covered by `span`, e.g.
+///
+/// ```text
+/// 1 + foo
+///     ^^^
+/// ```
+///
+/// `indent` is the number of columns printed before the input (the length of the prompt).
+/// An empty span gets a single caret.
+fn underline(input: &str, span: &Span, indent: usize) -> String {
+    let column = input.char_indices().take_while(|&(i, _)| i < span.start).count();
+    let width = input
+        .char_indices()
+        .filter(|&(i, _)| span.start <= i && i < span.end)
+        .count()
+        .max(1);
+
+    format!("{}{}", " ".repeat(indent + column), "^".repeat(width))
+}
+
+#[cfg(test)]
+mod underline_tests {
+    use super::underline;
+
+    #[test]
+    fn carets_under_span() {
+        assert_eq!(underline("1 + foo", &(4..7), 0), "    ^^^");
+        assert_eq!(underline("1 + foo", &(4..7), 3), "       ^^^");
+    }
+
+    #[test]
+    fn empty_span_gets_one_caret() {
+        assert_eq!(underline("1 +", &(3..3), 0), "   ^");
+    }
+
+    #[test]
+    fn columns_are_counted_in_characters() {
+        // `¦` is two bytes long
+        assert_eq!(underline("1 + ¦", &(4..6), 0), "    ^");
+        assert_eq!(underline("¦ + a", &(5..6), 0), "    ^");
+    }
+}